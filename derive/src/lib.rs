@@ -0,0 +1,57 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Index};
+
+// 为结构体生成 rsutils::json_merge::Mergeable 实现，逐个字段调用 merge_from
+#[proc_macro_derive(Mergeable)]
+pub fn derive_mergeable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+
+    // 泛型结构体的每个字段类型都需要实现 Mergeable，如 struct W<T> { x: T } 要求 T: Mergeable
+    let mut generics = input.generics.clone();
+    if !generics.params.is_empty() {
+        if let Data::Struct(s) = &input.data {
+            let where_clause = generics.make_where_clause();
+            for f in s.fields.iter() {
+                let ty = &f.ty;
+                where_clause.predicates.push(parse_quote!(#ty: ::rsutils::json_merge::Mergeable));
+            }
+        }
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(fields) => {
+                let names = fields.named.iter().map(|f| &f.ident);
+                quote! {
+                    #( ::rsutils::json_merge::Mergeable::merge_from(&mut self.#names, overlay.#names); )*
+                }
+            }
+            Fields::Unnamed(fields) => {
+                let indexes = (0..fields.unnamed.len()).map(Index::from);
+                quote! {
+                    #( ::rsutils::json_merge::Mergeable::merge_from(&mut self.#indexes, overlay.#indexes); )*
+                }
+            }
+            Fields::Unit => quote! {
+                let _ = overlay;
+            },
+        },
+        _ => {
+            return syn::Error::new_spanned(name, "Mergeable can only be derived for structs")
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    let expanded = quote! {
+        impl #impl_generics ::rsutils::json_merge::Mergeable for #name #ty_generics #where_clause {
+            fn merge_from(&mut self, overlay: Self) {
+                #body
+            }
+        }
+    };
+    expanded.into()
+}
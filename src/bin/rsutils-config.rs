@@ -0,0 +1,195 @@
+use anyhow::anyhow;
+use serde_json::Value;
+use structopt::StructOpt;
+
+use rsutils::config::{self, Layer};
+use rsutils::{json_diff, json_pointer};
+
+#[derive(StructOpt)]
+#[structopt(name = "rsutils-config", about = "Inspect layered json configs loaded by rsutils::config")]
+struct Opt {
+    /// Default config: file path, json content, "-" for stdin or /dev/fd/N
+    #[structopt(short, long)]
+    default: String,
+
+    /// User config: file path, json content, "-" for stdin or /dev/fd/N
+    #[structopt(short, long)]
+    user: Option<String>,
+
+    /// Print secrets (password, token, ...) as is
+    #[structopt(long)]
+    no_redact: bool,
+
+    /// Print config loading logs to stderr, layers in the logs are not redacted
+    #[structopt(short, long)]
+    verbose: bool,
+
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Print the final merged config
+    Show,
+    /// Show which layer set the value at a json pointer or dotted path
+    Explain { path: String },
+    /// Parse all layers and report errors
+    Validate,
+    /// Print what the user layer changes compared to default
+    Diff,
+}
+
+fn main() -> anyhow::Result<()> {
+    let opt = Opt::from_args();
+    config::set_quiet(!opt.verbose);
+
+    match &opt.cmd {
+        Command::Show => {
+            let layers = config::load_layers::<Value>(Some(opt.default.clone()), opt.user.clone(), None)?;
+            print_value(&opt, config::merge_layers(layers));
+        }
+        Command::Explain { path } => {
+            let layers = config::load_layers::<Value>(Some(opt.default.clone()), opt.user.clone(), None)?;
+            explain(&opt, layers, path)?;
+        }
+        Command::Validate => {
+            let mut failed = 0;
+            let sources = vec![("default", Some(opt.default.clone())), ("user", opt.user.clone())];
+            for (name, src) in sources {
+                if let Some(src) = src {
+                    match config::read_layer(name, src) {
+                        Ok(layer) => println!("{} OK: {}", name, layer.source),
+                        Err(e) => {
+                            println!("{} FAILED: {}", name, e);
+                            failed += 1;
+                        }
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow!("{} layer(s) failed to parse", failed));
+            }
+        }
+        Command::Diff => {
+            let layers = config::load_layers::<Value>(Some(opt.default.clone()), opt.user.clone(), None)?;
+            let default = layers[0].value.clone();
+            let merged = config::merge_layers(layers);
+            match json_diff::diff(&default, &merged) {
+                Some(d) => print_value(&opt, d),
+                None => println!("no changes"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_value(opt: &Opt, mut value: Value) {
+    if !opt.no_redact {
+        config::redact(&mut value, config::REDACT_KEYS);
+    }
+    println!("{}", serde_json::to_string_pretty(&value).unwrap());
+}
+
+// 一层中 path 的状态
+enum Lookup {
+    NotSet,
+    // null 删除了这个值或它的上级
+    Deleted,
+    // 上级 path[..n] 被设置为非对象，合并后这个值不再存在
+    Replaced(usize, Value),
+    Set(Value),
+}
+
+// 最底层（first 为 true）整体作为合并的起点，其中的 null 会保留在最终配置中，不表示删除
+fn lookup(value: &Value, segments: &[String], first: bool) -> Lookup {
+    let mut cur = value;
+    for (n, seg) in segments.iter().enumerate() {
+        match cur {
+            Value::Object(obj) => match obj.get(seg) {
+                Some(v) => cur = v,
+                None => return Lookup::NotSet,
+            },
+            v => return Lookup::Replaced(n, v.clone()),
+        }
+        if cur.is_null() && !first {
+            return Lookup::Deleted;
+        }
+    }
+    Lookup::Set(cur.clone())
+}
+
+// key 为 v 在上级对象中的名字，根没有名字
+fn redacted(opt: &Opt, key: Option<&String>, mut v: Value) -> Value {
+    if opt.no_redact {
+        return v;
+    }
+    match key {
+        Some(key) => {
+            // 套一层对象，让 redact 能根据 key 名判断
+            let mut wrapper = Value::Object(std::iter::once((key.clone(), v)).collect());
+            config::redact(&mut wrapper, config::REDACT_KEYS);
+            wrapper[key].take()
+        }
+        None => {
+            config::redact(&mut v, config::REDACT_KEYS);
+            v
+        }
+    }
+}
+
+// path 为空时解释整个配置
+fn explain(opt: &Opt, layers: Vec<Layer>, path: &str) -> anyhow::Result<()> {
+    let segments = json_pointer::parse_path(path)?;
+
+    let mut set_by = None;
+    for (i, layer) in layers.iter().enumerate() {
+        match lookup(&layer.value, &segments, i == 0) {
+            Lookup::NotSet => {}
+            Lookup::Deleted => {
+                println!("{} ({}): deleted", layer.name, layer.source);
+                set_by = None;
+            }
+            Lookup::Replaced(n, v) => {
+                let v = redacted(opt, n.checked_sub(1).map(|i| &segments[i]), v);
+                let parent = json_pointer::format(&segments[..n]);
+                println!("{} ({}): replaced by {} at {:?}", layer.name, layer.source, v, parent);
+                set_by = None;
+            }
+            Lookup::Set(v) => {
+                let v = redacted(opt, segments.last(), v);
+                println!("{} ({}): {}", layer.name, layer.source, v);
+                set_by = Some(layer.name.clone());
+            }
+        }
+    }
+
+    match set_by {
+        Some(name) => println!("final value set by: {}", name),
+        None => println!("{:?} is not set", path),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_lookup() {
+        let path = json_pointer::parse_path("server.port").unwrap();
+        assert!(matches!(lookup(&json!({"server": {"port": 80}}), &path, false), Lookup::Set(v) if v == json!(80)));
+        assert!(matches!(lookup(&json!({"server": {}}), &path, false), Lookup::NotSet));
+        assert!(matches!(lookup(&json!({"server": null}), &path, false), Lookup::Deleted));
+        // 上级被替换为非对象，合并后 server.port 不存在
+        assert!(matches!(lookup(&json!({"server": "off"}), &path, false), Lookup::Replaced(1, v) if v == json!("off")));
+        assert!(matches!(lookup(&json!({"a": 1}), &[], false), Lookup::Set(v) if v == json!({"a": 1})));
+
+        // 最底层中的 null 保留在最终配置中
+        assert!(matches!(lookup(&json!({"server": {"port": null}}), &path, true), Lookup::Set(Value::Null)));
+        assert!(matches!(lookup(&json!({"server": null}), &path, true), Lookup::Replaced(1, Value::Null)));
+        assert!(matches!(lookup(&json!({"server": {"port": null}}), &path, false), Lookup::Deleted));
+    }
+}
@@ -1,98 +1,389 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
 use json_comments::StripComments;
 use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 use std::fmt::Debug;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use crate::json_merge;
 
-// default 和 user 可传入 json 文件路径或 json 字符串
-// cmdline 传入 structopt 解析的命令行结构
-// 优先级：cmdline > user > default
-pub fn load<'a, T>(
+// 名字中包含这些字符串的 key 在输出配置时会被隐藏
+pub const REDACT_KEYS: &[&str] = &["password", "passwd", "secret", "token", "credential", "private_key", "api_key"];
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+// 关闭加载配置时输出到 stderr 的日志，日志中包含各层的原始内容（密码等不会隐藏）
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub(crate) fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+// 与 eprintln! 相同，set_quiet(true) 后不输出
+macro_rules! log {
+    ($($arg:tt)*) => {
+        if !$crate::config::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+#[cfg(feature = "tokio")]
+pub(crate) use log;
+
+// 配置的一层，name 为 default/user/cmdline，source 为文件路径或 <inline>
+pub struct Layer {
+    pub name: String,
+    pub source: String,
+    pub value: Value,
+}
+
+// 未指定 default 时使用可执行文件所在目录下的 conf/<exe_name>.json.default
+pub fn default_path() -> String {
+    let mut path = std::env::current_exe().unwrap();
+    let exe_name = path.file_stem().unwrap().to_str().unwrap().to_string();
+    path.pop();
+    path.push("conf");
+    path.push(format!("{}.{}", exe_name, "json.default"));
+    path.to_str().unwrap().to_string()
+}
+
+pub fn parse_jsonc(s: &str) -> serde_json::Result<Value> {
+    let stripped = StripComments::new(s.as_bytes());
+    serde_json::from_reader::<StripComments<&[u8]>, Value>(stripped)
+}
+
+// src 可以是 json 文件路径或 json 字符串
+// "-" 表示从标准输入读取，/dev/fd/N 表示从已打开的文件描述符读取（如管道）
+pub fn read_layer(name: &str, src: String) -> anyhow::Result<Layer> {
+    if src == "-" {
+        return read_layer_from(name, "<stdin>", std::io::stdin());
+    }
+    if src.starts_with("/dev/fd/") {
+        // 这类来源读失败时不能再当作 json 内容解析
+        let f = std::fs::File::open(&src).context(format!("open conf {} {}", name, src))?;
+        return read_layer_from(name, &src, f);
+    }
+
+    let (s, source) = match std::fs::read_to_string(&src) {
+        Ok(s) => {
+            log!("read {} as file OK:{}", name, s);
+            (s, src)
+        }
+        Err(e) => {
+            log!("read {} as file err, try parse as json content:{:?}", name, e);
+            (src, "<inline>".to_string())
+        }
+    };
+    parse_layer(name, source, &s)
+}
+
+pub fn read_layer_from<R: Read>(name: &str, source: &str, mut reader: R) -> anyhow::Result<Layer> {
+    let mut s = String::new();
+    reader.read_to_string(&mut s).context(format!("read conf {} from {}", name, source))?;
+    log!("read {} from {} OK:{}", name, source, s);
+    parse_layer(name, source.to_string(), &s)
+}
+
+pub(crate) fn parse_layer(name: &str, source: String, s: &str) -> anyhow::Result<Layer> {
+    match parse_jsonc(s) {
+        Ok(value) => Ok(Layer { name: name.to_string(), source, value }),
+        Err(e) => Err(anyhow!("decode conf {} FAILED! {}", name, e)),
+    }
+}
+
+// 按优先级从低到高返回各层配置：default, user, cmdline
+pub fn load_layers<T>(
     default: Option<String>,
     user: Option<String>,
     cmdline: Option<T>,
-) -> anyhow::Result<T>
+) -> anyhow::Result<Vec<Layer>>
 where
-    T: Serialize + DeserializeOwned + Debug,
+    T: Serialize,
 {
-    eprintln!("default:{:?}", default);
-    eprintln!("user:{:?}", user);
-
     let default = if let Some(v) = default {
         v
     } else {
-        let mut path = std::env::current_exe().unwrap();
-        let exe_name = path.file_stem().unwrap().to_str().unwrap().to_string();
-        path.pop();
-        path.push("conf");
-        path.push(format!("{}.{}", exe_name, "json.default"));
-        let v = path.to_str().unwrap().to_string();
-        eprintln!("conf_default not set, use:{}", v);
+        let v = default_path();
+        log!("conf_default not set, use:{}", v);
         v
     };
 
-    let mut cfg;
-    let mut default_str = match std::fs::read_to_string(&default) {
-        Ok(s) => {
-            eprintln!("read default as file OK:{}", s);
-            s
-        }
-        Err(e) => {
-            eprintln!("read default as file err, try parse as json content:{:?}", e);
-            default
+    let mut layers = vec![];
+    push_layers(&mut layers, Some(default), user, cmdline)?;
+    Ok(layers)
+}
+
+// 与 load_layers 相同，但以 T::default() 作为最底层
+// 未指定 default 且 conf/<exe_name>.json.default 不存在时跳过 default 层
+pub fn load_layers_or_default<T>(
+    default: Option<String>,
+    user: Option<String>,
+    cmdline: Option<T>,
+) -> anyhow::Result<Vec<Layer>>
+where
+    T: Serialize + Default,
+{
+    let default = if let Some(v) = default {
+        Some(v)
+    } else {
+        let v = default_path();
+        if std::path::Path::new(&v).exists() {
+            log!("conf_default not set, use:{}", v);
+            Some(v)
+        } else {
+            log!("conf_default not set and {} not exists, use T::default()", v);
+            None
         }
     };
-    let stripped = StripComments::new(default_str.as_bytes());
-    let r = serde_json::from_reader::<StripComments<&[u8]>, serde_json::Value>(stripped);
-    match r {
-        Ok(cfg_default) => {
-            cfg = cfg_default;
-        }
-        Err(e) => {
-            return Err(anyhow!("decode conf default FAILED! {}", e));
-        }
+
+    let value = serde_json::to_value(T::default()).map_err(|e| anyhow!("encode T::default() FAILED! {}", e))?;
+    log!("================================> conf T::default():\n{:#?}", value);
+    let mut layers = vec![Layer { name: "builtin".to_string(), source: "<T::default()>".to_string(), value }];
+    push_layers(&mut layers, default, user, cmdline)?;
+    Ok(layers)
+}
+
+fn push_layers<T>(
+    layers: &mut Vec<Layer>,
+    default: Option<String>,
+    user: Option<String>,
+    cmdline: Option<T>,
+) -> anyhow::Result<()>
+where
+    T: Serialize,
+{
+    log!("default:{:?}", default);
+    log!("user:{:?}", user);
+
+    if default.as_deref() == Some("-") && user.as_deref() == Some("-") {
+        return Err(anyhow!("default and user can not both read from stdin"));
+    }
+
+    if let Some(default) = default {
+        let layer = read_layer("default", default)?;
+        log!("================================> conf default:\n{:#?}", layer.value);
+        layers.push(layer);
     }
-    eprintln!("================================> conf default:\n{:#?}", cfg);
 
     if let Some(user) = user {
-        let mut user_str = match std::fs::read_to_string(&user) {
-            Ok(s) => {
-                eprintln!("read user as file OK:{}", s);
-                s
-            }
-            Err(e) => {
-                eprintln!("read user as file err, try parse as json content:{:?}", e);
-                user
-            }
-        };
-        let stripped = StripComments::new(user_str.as_bytes());
-        let r = serde_json::from_reader::<StripComments<&[u8]>, serde_json::Value>(stripped);
-        match r {
-            Ok(cfg_user) => {
-                eprintln!("================================> conf user:\n{:#?}", cfg_user);
-                json_merge::merge(&mut cfg, cfg_user);
-            }
-            Err(e) => {
-                return Err(anyhow!("decode conf user FAILED! {}", e));
-            }
-        }
-        eprintln!("================================> conf merge user:\n{:#?}", cfg);
+        let layer = read_layer("user", user)?;
+        log!("================================> conf user:\n{:#?}", layer.value);
+        layers.push(layer);
     } else {
         // allow no conf user, but print warnings
-        eprintln!("no conf user specified");
+        log!("no conf user specified");
     }
 
     if let Some(c) = cmdline {
-        let cfg_cmdline = serde_json::to_value(c).unwrap();
-        eprintln!("================================> conf cmdline:\n{:#?}", cfg_cmdline);
+        layers.push(cmdline_layer(c));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn cmdline_layer<T: Serialize>(cmdline: T) -> Layer {
+    let cfg_cmdline = serde_json::to_value(cmdline).unwrap();
+    log!("================================> conf cmdline:\n{:#?}", cfg_cmdline);
+    Layer { name: "cmdline".to_string(), source: "<cmdline>".to_string(), value: cfg_cmdline }
+}
 
-        json_merge::merge(&mut cfg, cfg_cmdline);
-        eprintln!("================================> conf merge cmdline:\n{:#?}", cfg);
+// 按顺序合并各层，后面的层改变值的类型或覆盖 user 等层设置的值时打印警告
+pub fn merge_layers(layers: Vec<Layer>) -> Value {
+    let mut cfg = json_merge::LayeredMerge::new(Value::Null, json_merge::CheckOptions::new());
+    for layer in layers {
+        // 未设置 fail_on_type_change，不会返回错误
+        let conflicts = match layer.name.as_str() {
+            "builtin" | "default" => cfg.merge_default(layer.value).unwrap(),
+            name => cfg.merge(name, layer.value).unwrap(),
+        };
+        for c in conflicts {
+            log!("conf {} conflict at {}: {:?}", layer.name, c.pointer, c.kind);
+        }
+        log!("================================> conf merge {}:\n{:#?}", layer.name, cfg.value());
     }
+    cfg.into_value()
+}
 
-    let cfg: T = serde_json::from_value(cfg).unwrap();
-    eprintln!("================================> conf final:\n{:#?}", cfg);
+// 将名字匹配 keys 的字段替换为 "******"
+pub fn redact(value: &mut Value, keys: &[&str]) {
+    match value {
+        Value::Object(obj) => {
+            for (k, v) in obj.iter_mut() {
+                let lower = k.to_lowercase();
+                if keys.iter().any(|r| lower.contains(r)) && !v.is_object() {
+                    *v = Value::String("******".to_string());
+                } else {
+                    redact(v, keys);
+                }
+            }
+        }
+        Value::Array(arr) => {
+            for v in arr {
+                redact(v, keys);
+            }
+        }
+        _ => {}
+    }
+}
+
+// default 和 user 可传入 json 文件路径或 json 字符串
+// cmdline 传入 structopt 解析的命令行结构
+// 优先级：cmdline > user > default
+pub fn load<T>(
+    default: Option<String>,
+    user: Option<String>,
+    cmdline: Option<T>,
+) -> anyhow::Result<T>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    let layers = load_layers(default, user, cmdline)?;
+    let cfg = merge_layers(layers);
+
+    let cfg: T = serde_json::from_value(cfg).map_err(|e| anyhow!("decode conf final FAILED! {}", e))?;
+    log!("================================> conf final:\n{:#?}", cfg);
 
     Ok(cfg)
 }
+
+// 与 load 相同，但 default 文件可以不存在，此时以 T::default() 作为最底层
+// 优先级：cmdline > user > default > T::default()
+pub fn load_or_default<T>(
+    default: Option<String>,
+    user: Option<String>,
+    cmdline: Option<T>,
+) -> anyhow::Result<T>
+where
+    T: Serialize + DeserializeOwned + Debug + Default,
+{
+    let layers = load_layers_or_default(default, user, cmdline)?;
+    let cfg = merge_layers(layers);
+
+    let cfg: T = serde_json::from_value(cfg).map_err(|e| anyhow!("decode conf final FAILED! {}", e))?;
+    log!("================================> conf final:\n{:#?}", cfg);
+
+    Ok(cfg)
+}
+
+// 将 T::default() 写为 json.default 文件，path 为 None 时写到 conf/<exe_name>.json.default
+// 返回写入的文件路径
+pub fn write_default<T>(path: Option<String>) -> anyhow::Result<String>
+where
+    T: Serialize + Default,
+{
+    let path = path.unwrap_or_else(default_path);
+    if let Some(dir) = std::path::Path::new(&path).parent() {
+        std::fs::create_dir_all(dir).context(format!("create dir {:?}", dir))?;
+    }
+    let s = serde_json::to_string_pretty(&T::default())?;
+    std::fs::write(&path, s + "\n").context(format!("write {}", path))?;
+    Ok(path)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::io::Write;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Conf {
+        host: String,
+        port: u16,
+    }
+
+    impl Default for Conf {
+        fn default() -> Self {
+            Conf { host: "0.0.0.0".to_string(), port: 80 }
+        }
+    }
+
+    #[test]
+    fn test_read_layer_from_reader() {
+        // 模拟从 stdin 管道读取
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        writer.write_all(b"{\n  // comment\n  \"port\": 8080\n}").unwrap();
+        drop(writer);
+
+        let layer = read_layer_from("user", "<stdin>", reader).unwrap();
+        assert_eq!(layer.source, "<stdin>");
+        assert_eq!(layer.value, json!({"port": 8080}));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_load_from_fd() {
+        use std::os::unix::io::AsRawFd;
+
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        writer.write_all(br#"{"port": 9090}"#).unwrap();
+        drop(writer);
+
+        let user = format!("/dev/fd/{}", reader.as_raw_fd());
+        let default = r#"{"host": "127.0.0.1", "port": 80}"#.to_string();
+        let conf: Conf = load::<Conf>(Some(default), Some(user), None).unwrap();
+        assert_eq!(conf, Conf { host: "127.0.0.1".to_string(), port: 9090 });
+    }
+
+    #[test]
+    fn test_bad_fd() {
+        // 无效的 fd 应该报错，而不是被当作 json 内容解析
+        let r = read_layer("user", "/dev/fd/987654".to_string());
+        assert!(r.is_err());
+
+        let r = load_layers::<Conf>(Some("-".to_string()), Some("-".to_string()), None);
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn test_load_or_default() {
+        // 测试程序目录下没有 conf/*.json.default，直接使用 T::default()
+        let conf: Conf = load_or_default::<Conf>(None, Some(r#"{"port": 8080}"#.to_string()), None).unwrap();
+        assert_eq!(conf, Conf { host: "0.0.0.0".to_string(), port: 8080 });
+
+        // default 文件只需要包含部分字段
+        let default = r#"{"host": "127.0.0.1"}"#.to_string();
+        let conf: Conf = load_or_default::<Conf>(Some(default), None, None).unwrap();
+        assert_eq!(conf, Conf { host: "127.0.0.1".to_string(), port: 80 });
+    }
+
+    #[test]
+    fn test_write_default() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("rsutils-test-{}", std::process::id()));
+        path.push("conf.json.default");
+        let path = path.to_str().unwrap().to_string();
+
+        assert_eq!(write_default::<Conf>(Some(path.clone())).unwrap(), path);
+        let conf: Conf = load::<Conf>(Some(path.clone()), None, None).unwrap();
+        assert_eq!(conf, Conf::default());
+
+        std::fs::remove_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[test]
+    fn test_merge_layers_preserve_order() {
+        // 输出保持 default 中的顺序，user 新增的 key 追加到最后
+        let default = read_layer("default", r#"{"server": {"port": 80, "host": "0.0.0.0"}, "log": "info"}"#.to_string());
+        let user = read_layer("user", r#"{"extra": 1, "server": {"tls": true, "port": 8080}}"#.to_string());
+        let cfg = merge_layers(vec![default.unwrap(), user.unwrap()]);
+        assert_eq!(
+            serde_json::to_string_pretty(&cfg).unwrap(),
+            r#"{
+  "server": {
+    "port": 8080,
+    "host": "0.0.0.0",
+    "tls": true
+  },
+  "log": "info",
+  "extra": 1
+}"#
+        );
+    }
+}
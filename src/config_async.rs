@@ -0,0 +1,273 @@
+use anyhow::{anyhow, Context};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::sync::watch;
+
+use crate::config::{self, log, Layer};
+
+// config::load 的异步版本，文件读取不阻塞运行时，合并规则与 config::load 相同
+// 优先级：cmdline > user > default
+pub async fn load_async<T>(
+    default: Option<String>,
+    user: Option<String>,
+    cmdline: Option<T>,
+) -> anyhow::Result<T>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    let sources = Sources::new(default, user, cmdline).await?;
+    let cfg = sources.load().await?;
+    let cfg: T = serde_json::from_value(cfg).map_err(|e| anyhow!("decode conf final FAILED! {}", e))?;
+    log!("================================> conf final:\n{:#?}", cfg);
+    Ok(cfg)
+}
+
+// 加载配置并每隔 interval 检查 default 和 user 文件，文件变化且合并结果不同时推送新配置
+// 重新加载失败时保留旧配置，所有 Receiver 释放后后台任务退出
+// stdin 和 /dev/fd/N 只能读取一次，第一次读取的结果一直使用，不参与检查
+pub async fn watch<T>(
+    default: Option<String>,
+    user: Option<String>,
+    cmdline: Option<T>,
+    interval: Duration,
+) -> anyhow::Result<watch::Receiver<Arc<T>>>
+where
+    T: Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
+{
+    let sources = Sources::new(default, user, cmdline).await?;
+    let mut stamps = sources.stamps().await;
+    let mut cfg = sources.load().await?;
+    let first: T = serde_json::from_value(cfg.clone()).map_err(|e| anyhow!("decode conf final FAILED! {}", e))?;
+
+    let (tx, rx) = watch::channel(Arc::new(first));
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = tx.closed() => break,
+            }
+
+            let new_stamps = sources.stamps().await;
+            if new_stamps == stamps {
+                continue;
+            }
+            stamps = new_stamps;
+
+            let new_cfg = match sources.load().await {
+                Ok(v) => v,
+                Err(e) => {
+                    log!("reload conf FAILED, keep the old one! {:?}", e);
+                    continue;
+                }
+            };
+            if new_cfg == cfg {
+                continue;
+            }
+            match serde_json::from_value::<T>(new_cfg.clone()) {
+                Ok(v) => {
+                    log!("================================> conf reloaded:\n{:#?}", v);
+                    cfg = new_cfg;
+                    if tx.send(Arc::new(v)).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => log!("decode reloaded conf FAILED, keep the old one! {}", e),
+            }
+        }
+    });
+
+    Ok(rx)
+}
+
+struct Sources {
+    default: Source,
+    user: Option<Source>,
+    cmdline: Option<Value>,
+}
+
+enum Source {
+    // 文件路径或 json 内容，每次加载时重新读取
+    Reload(String),
+    // stdin 和 /dev/fd/N 只能读取一次，保存读取的结果
+    Once(Layer),
+}
+
+impl Source {
+    async fn new(name: &str, src: String) -> anyhow::Result<Source> {
+        if src == "-" || src.starts_with("/dev/fd/") {
+            Ok(Source::Once(read_layer(name, &src).await?))
+        } else {
+            Ok(Source::Reload(src))
+        }
+    }
+
+    async fn layer(&self, name: &str) -> anyhow::Result<Layer> {
+        match self {
+            Source::Reload(src) => read_layer(name, src).await,
+            Source::Once(l) => Ok(Layer { name: l.name.clone(), source: l.source.clone(), value: l.value.clone() }),
+        }
+    }
+
+    // 文件内容的 hash，非文件来源为 None
+    // 修改时间和大小不可靠：时间精度内大小不变的修改（如端口 8080 改为 9090）检查不到
+    async fn stamp(&self) -> Option<u64> {
+        let src = match self {
+            Source::Reload(src) => src,
+            Source::Once(_) => return None,
+        };
+        let content = tokio::fs::read(src).await.ok()?;
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+}
+
+impl Sources {
+    async fn new<T: Serialize>(default: Option<String>, user: Option<String>, cmdline: Option<T>) -> anyhow::Result<Sources> {
+        log!("default:{:?}", default);
+        log!("user:{:?}", user);
+
+        let default = if let Some(v) = default {
+            v
+        } else {
+            let v = config::default_path();
+            log!("conf_default not set, use:{}", v);
+            v
+        };
+        if default == "-" && user.as_deref() == Some("-") {
+            return Err(anyhow!("default and user can not both read from stdin"));
+        }
+
+        let default = Source::new("default", default).await?;
+        let user = match user {
+            Some(user) => Some(Source::new("user", user).await?),
+            None => None,
+        };
+        let cmdline = cmdline.map(|c| config::cmdline_layer(c).value);
+        Ok(Sources { default, user, cmdline })
+    }
+
+    async fn load(&self) -> anyhow::Result<Value> {
+        let mut layers = vec![self.default.layer("default").await?];
+        if let Some(user) = &self.user {
+            layers.push(user.layer("user").await?);
+        } else {
+            log!("no conf user specified");
+        }
+        if let Some(c) = &self.cmdline {
+            layers.push(Layer { name: "cmdline".to_string(), source: "<cmdline>".to_string(), value: c.clone() });
+        }
+        Ok(config::merge_layers(layers))
+    }
+
+    async fn stamps(&self) -> Vec<Option<u64>> {
+        let mut stamps = vec![self.default.stamp().await];
+        if let Some(user) = &self.user {
+            stamps.push(user.stamp().await);
+        }
+        stamps
+    }
+}
+
+// 与 config::read_layer 相同的来源规则
+async fn read_layer(name: &str, src: &str) -> anyhow::Result<Layer> {
+    let mut s = String::new();
+    if src == "-" {
+        tokio::io::stdin().read_to_string(&mut s).await.context(format!("read conf {} from <stdin>", name))?;
+        return config::parse_layer(name, "<stdin>".to_string(), &s);
+    }
+    if src.starts_with("/dev/fd/") {
+        let mut f = tokio::fs::File::open(src).await.context(format!("open conf {} {}", name, src))?;
+        f.read_to_string(&mut s).await.context(format!("read conf {} from {}", name, src))?;
+        return config::parse_layer(name, src.to_string(), &s);
+    }
+
+    match tokio::fs::read_to_string(src).await {
+        Ok(s) => {
+            log!("read {} as file OK:{}", name, s);
+            config::parse_layer(name, src.to_string(), &s)
+        }
+        Err(e) => {
+            log!("read {} as file err, try parse as json content:{:?}", name, e);
+            config::parse_layer(name, "<inline>".to_string(), src)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Conf {
+        host: String,
+        port: u16,
+    }
+
+    #[tokio::test]
+    async fn test_load_async() {
+        let default = r#"{"host": "0.0.0.0", /* comment */ "port": 80}"#.to_string();
+        let user = r#"{"port": 8080}"#.to_string();
+        let conf: Conf = load_async::<Conf>(Some(default), Some(user), None).await.unwrap();
+        assert_eq!(conf, Conf { host: "0.0.0.0".to_string(), port: 8080 });
+    }
+
+    #[tokio::test]
+    async fn test_watch_reload() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("rsutils-test-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let user = dir.join("user.json").to_str().unwrap().to_string();
+        std::fs::write(&user, r#"{"port": 8080}"#).unwrap();
+
+        let default = r#"{"host": "0.0.0.0", "port": 80}"#.to_string();
+        let mut rx = watch::<Conf>(Some(default), Some(user.clone()), None, Duration::from_millis(10))
+            .await
+            .unwrap();
+        assert_eq!(rx.borrow().port, 8080);
+
+        // 写入无法解析的内容，保留旧配置
+        std::fs::write(&user, r#"{"port": "#).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!rx.has_changed().unwrap());
+
+        std::fs::write(&user, r#"{"port": 9090, "host": "127.0.0.1"}"#).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), rx.changed()).await.unwrap().unwrap();
+        assert_eq!(**rx.borrow(), Conf { host: "127.0.0.1".to_string(), port: 9090 });
+
+        // 立即写入大小相同的内容，修改时间可能不变
+        rx.mark_unchanged();
+        std::fs::write(&user, r#"{"port": 9191, "host": "127.0.0.1"}"#).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), rx.changed()).await.unwrap().unwrap();
+        assert_eq!(rx.borrow().port, 9191);
+
+        // default 从管道读取，只读一次，user 修改后仍然能重新加载
+        #[cfg(unix)]
+        {
+            use std::io::Write;
+            use std::os::unix::io::AsRawFd;
+
+            let (reader, mut writer) = std::io::pipe().unwrap();
+            writer.write_all(br#"{"host": "10.0.0.1", "port": 80}"#).unwrap();
+            drop(writer);
+            let default = format!("/dev/fd/{}", reader.as_raw_fd());
+            let mut rx = watch::<Conf>(Some(default), Some(user.clone()), None, Duration::from_millis(10))
+                .await
+                .unwrap();
+            assert_eq!(rx.borrow().port, 9191);
+
+            std::fs::write(&user, r#"{"port": 7070}"#).unwrap();
+            tokio::time::timeout(Duration::from_secs(5), rx.changed()).await.unwrap().unwrap();
+            assert_eq!(**rx.borrow(), Conf { host: "10.0.0.1".to_string(), port: 7070 });
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
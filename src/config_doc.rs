@@ -0,0 +1,397 @@
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::parse_jsonc;
+use crate::json_pointer;
+
+// 从带注释的 *.json.default 文件生成配置说明文档
+// 每个 key 前面的 // 或 /* */ 注释（以及同一行末尾的注释）作为该 key 的说明
+pub struct DocEntry {
+    pub path: Vec<String>,
+    pub description: String,
+    pub default: Value,
+}
+
+impl DocEntry {
+    pub fn dotted(&self) -> String {
+        self.path.join(".")
+    }
+
+    pub fn pointer(&self) -> String {
+        json_pointer::format(&self.path)
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match &self.default {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(n) if n.is_f64() => "number",
+            Value::Number(_) => "integer",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }
+    }
+
+    // 对象的默认值由其子项描述，表格中不再重复输出
+    fn default_text(&self) -> String {
+        match &self.default {
+            Value::Object(_) => String::new(),
+            v => v.to_string(),
+        }
+    }
+}
+
+pub struct ConfigDoc {
+    pub entries: Vec<DocEntry>,
+    pub value: Value,
+}
+
+impl ConfigDoc {
+    pub fn parse(jsonc: &str) -> anyhow::Result<ConfigDoc> {
+        let value = parse_jsonc(jsonc).map_err(|e| anyhow!("decode jsonc FAILED! {}", e))?;
+
+        let mut entries = vec![];
+        for (path, description) in scan_comments(jsonc)? {
+            let default = value.pointer(&json_pointer::format(&path)).cloned().unwrap_or(Value::Null);
+            entries.push(DocEntry { path, description, default });
+        }
+
+        Ok(ConfigDoc { entries, value })
+    }
+
+    pub fn from_file(path: &str) -> anyhow::Result<ConfigDoc> {
+        let s = std::fs::read_to_string(path).context(format!("read {}", path))?;
+        ConfigDoc::parse(&s)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("| Path | Default | Type | Description |\n");
+        out.push_str("| --- | --- | --- | --- |\n");
+        for e in &self.entries {
+            out.push_str(&format!(
+                "| `{}` | {} | {} | {} |\n",
+                e.dotted(),
+                markdown_cell(&e.default_text()),
+                e.type_name(),
+                markdown_cell(&e.description)
+            ));
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::from("<table>\n");
+        out.push_str("<tr><th>Path</th><th>Default</th><th>Type</th><th>Description</th></tr>\n");
+        for e in &self.entries {
+            out.push_str(&format!(
+                "<tr><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                html_escape(&e.dotted()),
+                html_escape(&e.default_text()),
+                e.type_name(),
+                html_escape(&e.description).replace('\n', "<br>")
+            ));
+        }
+        out.push_str("</table>\n");
+        out
+    }
+
+    // 生成带注释的示例配置，key 的顺序与原文件一致
+    pub fn to_example(&self) -> String {
+        let mut out = String::new();
+        self.write_example(&mut out, &mut vec![], &self.value, 0);
+        out.push('\n');
+        out
+    }
+
+    fn write_example(&self, out: &mut String, path: &mut Vec<String>, value: &Value, level: usize) {
+        let obj = match value {
+            Value::Object(obj) => obj,
+            v => {
+                out.push_str(&pretty(v, level));
+                return;
+            }
+        };
+        if obj.is_empty() {
+            out.push_str("{}");
+            return;
+        }
+
+        // 先按文件中出现的顺序，再补上没有记录到的 key
+        let mut keys: Vec<&String> = vec![];
+        for e in &self.entries {
+            if e.path.len() == path.len() + 1 && e.path[..path.len()] == path[..] {
+                if let Some((k, _)) = obj.get_key_value(&e.path[path.len()]) {
+                    keys.push(k);
+                }
+            }
+        }
+        for k in obj.keys() {
+            if !keys.contains(&k) {
+                keys.push(k);
+            }
+        }
+
+        let indent = "    ".repeat(level + 1);
+        out.push_str("{\n");
+        for (i, k) in keys.iter().enumerate() {
+            path.push(k.to_string());
+            if let Some(e) = self.entries.iter().find(|e| e.path == *path) {
+                for line in e.description.lines() {
+                    out.push_str(&format!("{}// {}\n", indent, line));
+                }
+            }
+            out.push_str(&format!("{}{}: ", indent, Value::String(k.to_string())));
+            self.write_example(out, path, &obj[k.as_str()], level + 1);
+            path.pop();
+            if i + 1 < keys.len() {
+                out.push(',');
+            }
+            out.push('\n');
+        }
+        out.push_str(&"    ".repeat(level));
+        out.push('}');
+    }
+}
+
+enum Frame {
+    Object(Option<String>),
+    Array,
+}
+
+// 扫描 jsonc 文本，返回 (key 路径, 注释) 列表，顺序与文件中出现的顺序一致
+// 数组内部的对象不是配置项，不做记录
+fn scan_comments(text: &str) -> anyhow::Result<Vec<(Vec<String>, String)>> {
+    let mut result: Vec<(Vec<String>, String)> = vec![];
+    let mut stack: Vec<Frame> = vec![];
+    let mut pending: Vec<String> = vec![];
+    let mut expect_key = false;
+    // 同一行中最近记录的 key，用于挂接行尾注释
+    let mut same_line: Option<usize> = None;
+    // 最近记录的 key，以及 stack 中各层对象、数组所属的 key，容器结束后行尾注释挂接到所属的 key
+    let mut last_key: Option<usize> = None;
+    let mut owners: Vec<Option<usize>> = vec![];
+
+    let mut chars = text.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '\n' => same_line = None,
+            '/' => {
+                let mut comment = String::new();
+                match chars.next() {
+                    Some((_, '/')) => {
+                        while let Some(&(_, c)) = chars.peek() {
+                            if c == '\n' {
+                                break;
+                            }
+                            comment.push(c);
+                            chars.next();
+                        }
+                    }
+                    Some((_, '*')) => {
+                        let mut prev = ' ';
+                        loop {
+                            match chars.next() {
+                                Some((_, '/')) if prev == '*' => break,
+                                Some((_, c)) => {
+                                    comment.push(c);
+                                    prev = c;
+                                }
+                                None => return Err(anyhow!("unterminated comment at {}", pos)),
+                            }
+                        }
+                        comment.pop();
+                    }
+                    _ => return Err(anyhow!("unexpected '/' at {}", pos)),
+                }
+                let lines = comment_lines(&comment);
+                match same_line {
+                    Some(idx) => {
+                        let desc = &mut result[idx].1;
+                        if !desc.is_empty() {
+                            desc.push('\n');
+                        }
+                        desc.push_str(&lines.join("\n"));
+                    }
+                    None => pending.extend(lines),
+                }
+            }
+            '"' => {
+                let mut end = pos + 1;
+                while let Some((i, c)) = chars.next() {
+                    end = i + c.len_utf8();
+                    if c == '\\' {
+                        chars.next();
+                    } else if c == '"' {
+                        break;
+                    }
+                }
+                let in_array = stack.iter().any(|f| matches!(f, Frame::Array));
+                if let Some(Frame::Object(key)) = stack.last_mut() {
+                    if expect_key {
+                        let k: String = serde_json::from_str(&text[pos..end])
+                            .map_err(|e| anyhow!("decode key at {} FAILED! {}", pos, e))?;
+                        *key = Some(k);
+                        expect_key = false;
+                        if !in_array {
+                            let path = stack
+                                .iter()
+                                .filter_map(|f| match f {
+                                    Frame::Object(k) => k.clone(),
+                                    Frame::Array => None,
+                                })
+                                .collect();
+                            result.push((path, pending.join("\n").trim().to_string()));
+                            same_line = Some(result.len() - 1);
+                        }
+                        last_key = if in_array { None } else { same_line };
+                        pending.clear();
+                    }
+                }
+            }
+            '{' => {
+                owners.push(if matches!(stack.last(), Some(Frame::Object(_))) { last_key } else { None });
+                stack.push(Frame::Object(None));
+                expect_key = true;
+                pending.clear();
+            }
+            '[' => {
+                owners.push(if matches!(stack.last(), Some(Frame::Object(_))) { last_key } else { None });
+                stack.push(Frame::Array);
+                expect_key = false;
+            }
+            '}' | ']' => {
+                stack.pop();
+                same_line = owners.pop().flatten();
+                expect_key = false;
+                pending.clear();
+            }
+            ',' => expect_key = matches!(stack.last(), Some(Frame::Object(_))),
+            _ => {}
+        }
+    }
+
+    Ok(result)
+}
+
+// 去掉注释行首的空白和块注释中常见的 '*' 前缀
+fn comment_lines(comment: &str) -> Vec<String> {
+    comment
+        .lines()
+        .map(|l| {
+            let l = l.trim();
+            let l = l.trim_start_matches('/');
+            let l = l.strip_prefix('*').unwrap_or(l);
+            l.trim().to_string()
+        })
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+fn pretty(v: &Value, level: usize) -> String {
+    let mut buf = vec![];
+    let fmt = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    v.serialize(&mut serde_json::Serializer::with_formatter(&mut buf, fmt)).unwrap();
+    let s = String::from_utf8(buf).unwrap();
+    s.replace('\n', &format!("\n{}", "    ".repeat(level)))
+}
+
+fn markdown_cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', "<br>")
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    const DEFAULT: &str = r#"
+// 示例服务的默认配置
+{
+    // 服务名称
+    "name": "demo",
+    "server": {
+        // 监听地址
+        "host": "0.0.0.0",
+        "port": 8080, // 监听端口
+        /*
+         * TLS 配置
+         * 不需要时留空
+         */
+        "tls": {},
+        "limits": {"rate": 100, "burst": 10}, // 限流
+        "ids": [1, 2] /* 允许的 id */
+    },
+    // 上游列表
+    "upstreams": [
+        {"name": "a", "weight": 1} // 数组中的元素不是配置项
+    ],
+    "ratio": 0.5
+}
+"#;
+
+    #[test]
+    fn test_parse_comments() {
+        let doc = ConfigDoc::parse(DEFAULT).unwrap();
+        let got: Vec<(String, &str)> =
+            doc.entries.iter().map(|e| (e.dotted(), e.description.as_str())).collect();
+        assert_eq!(
+            got,
+            vec![
+                ("name".to_string(), "服务名称"),
+                ("server".to_string(), ""),
+                ("server.host".to_string(), "监听地址"),
+                ("server.port".to_string(), "监听端口"),
+                ("server.tls".to_string(), "TLS 配置\n不需要时留空"),
+                ("server.limits".to_string(), "限流"),
+                ("server.limits.rate".to_string(), ""),
+                ("server.limits.burst".to_string(), ""),
+                ("server.ids".to_string(), "允许的 id"),
+                ("upstreams".to_string(), "上游列表"),
+                ("ratio".to_string(), ""),
+            ]
+        );
+
+        let port = &doc.entries[3];
+        assert_eq!(port.pointer(), "/server/port");
+        assert_eq!(port.default, json!(8080));
+        assert_eq!(port.type_name(), "integer");
+        assert_eq!(doc.entries[10].type_name(), "number");
+    }
+
+    #[test]
+    fn test_markdown_and_html() {
+        let doc = ConfigDoc::parse(DEFAULT).unwrap();
+
+        let md = doc.to_markdown();
+        assert!(md.contains("| `server.port` | 8080 | integer | 监听端口 |\n"));
+        assert!(md.contains("| `server.tls` |  | object | TLS 配置<br>不需要时留空 |\n"));
+
+        let html = doc.to_html();
+        assert!(html.contains("<td><code>name</code></td><td>&quot;demo&quot;</td><td>string</td><td>服务名称</td>"));
+    }
+
+    #[test]
+    fn test_example_round_trip() {
+        let doc = ConfigDoc::parse(DEFAULT).unwrap();
+        let example = doc.to_example();
+        assert!(example.contains("    // 服务名称\n    \"name\": \"demo\",\n"));
+        assert!(example.contains("        // 监听端口\n        \"port\": 8080,\n"));
+
+        // 生成的示例配置重新解析后内容和注释都不变
+        let doc2 = ConfigDoc::parse(&example).unwrap();
+        assert_eq!(doc2.value, doc.value);
+        let descs = |d: &ConfigDoc| -> Vec<(String, String)> {
+            d.entries.iter().map(|e| (e.dotted(), e.description.clone())).collect()
+        };
+        assert_eq!(descs(&doc2), descs(&doc));
+    }
+}
@@ -0,0 +1,201 @@
+use serde_json::{Map, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// 不使用递归的 Value 操作，用于嵌套很深的文档
+// serde_json::Value 自带的 drop、clone、== 都是递归实现，嵌套几万层时会栈溢出
+
+pub(crate) fn drop(v: Value) {
+    let mut stack = vec![v];
+    while let Some(v) = stack.pop() {
+        match v {
+            Value::Array(arr) => stack.extend(arr),
+            Value::Object(obj) => stack.extend(obj.into_iter().map(|(_, v)| v)),
+            _ => {}
+        }
+    }
+}
+
+pub(crate) fn eq(a: &Value, b: &Value) -> bool {
+    eq_with(a, b, false)
+}
+
+// 与 eq 相同，但数字按数值比较，1 与 1.0 相等
+pub(crate) fn eq_numeric(a: &Value, b: &Value) -> bool {
+    eq_with(a, b, true)
+}
+
+fn eq_with(a: &Value, b: &Value, numeric: bool) -> bool {
+    let mut stack = vec![(a, b)];
+    while let Some((a, b)) = stack.pop() {
+        match (a, b) {
+            (Value::Array(x), Value::Array(y)) => {
+                if x.len() != y.len() {
+                    return false;
+                }
+                stack.extend(x.iter().zip(y));
+            }
+            (Value::Object(x), Value::Object(y)) => {
+                if x.len() != y.len() {
+                    return false;
+                }
+                for (k, v) in x {
+                    match y.get(k) {
+                        Some(w) => stack.push((v, w)),
+                        None => return false,
+                    }
+                }
+            }
+            (Value::Array(_), _) | (Value::Object(_), _) | (_, Value::Array(_)) | (_, Value::Object(_)) => {
+                return false;
+            }
+            (Value::Number(x), Value::Number(y)) if numeric && (x.is_f64() || y.is_f64()) => {
+                if x.as_f64() != y.as_f64() {
+                    return false;
+                }
+            }
+            (a, b) => {
+                if a != b {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+// 与 eq 一致的 hash，eq 为 true 的两个值 hash 相同
+// 每个节点按它的路径和内容求 hash 后相加，对象的 hash 与 key 的顺序无关
+pub(crate) fn hash(v: &Value) -> u64 {
+    fn mix<T: Hash + ?Sized>(ctx: u64, x: &T) -> u64 {
+        let mut h = DefaultHasher::new();
+        ctx.hash(&mut h);
+        x.hash(&mut h);
+        h.finish()
+    }
+
+    let mut sum = 0u64;
+    let mut stack = vec![(v, 0u64)];
+    while let Some((v, ctx)) = stack.pop() {
+        let h = match v {
+            Value::Null => mix(ctx, &0u8),
+            Value::Bool(b) => mix(ctx, &(1u8, b)),
+            Value::Number(n) => mix(ctx, &(2u8, n)),
+            Value::String(s) => mix(ctx, &(3u8, s)),
+            Value::Array(arr) => {
+                stack.extend(arr.iter().enumerate().map(|(i, c)| (c, mix(ctx, &i))));
+                mix(ctx, &(4u8, arr.len()))
+            }
+            Value::Object(obj) => {
+                stack.extend(obj.iter().map(|(k, c)| (c, mix(ctx, k))));
+                mix(ctx, &(5u8, obj.len()))
+            }
+        };
+        sum = sum.wrapping_add(h);
+    }
+    sum
+}
+
+// 正在复制的容器，第一个字段为它在父对象中的 key
+enum Frame<'a> {
+    Array(Option<String>, Vec<Value>, std::slice::Iter<'a, Value>),
+    Object(Option<String>, Map<String, Value>, serde_json::map::Iter<'a>),
+}
+
+impl<'a> Frame<'a> {
+    fn next_child(&mut self) -> Option<(Option<&'a String>, &'a Value)> {
+        match self {
+            Frame::Array(_, _, it) => it.next().map(|v| (None, v)),
+            Frame::Object(_, _, it) => it.next().map(|(k, v)| (Some(k), v)),
+        }
+    }
+
+    fn insert(&mut self, key: Option<String>, v: Value) {
+        match self {
+            Frame::Array(_, arr, _) => arr.push(v),
+            Frame::Object(_, obj, _) => {
+                obj.insert(key.unwrap(), v);
+            }
+        }
+    }
+
+    fn finish(self) -> (Option<String>, Value) {
+        match self {
+            Frame::Array(key, arr, _) => (key, Value::Array(arr)),
+            Frame::Object(key, obj, _) => (key, Value::Object(obj)),
+        }
+    }
+}
+
+pub(crate) fn clone(v: &Value) -> Value {
+    let mut stack: Vec<Frame> = vec![];
+    let mut next: Option<(Option<&String>, &Value)> = Some((None, v));
+    loop {
+        // 标量直接复制，容器入栈后逐个处理子节点
+        if let Some((key, v)) = next.take() {
+            let value = match v {
+                Value::Array(arr) => {
+                    stack.push(Frame::Array(key.cloned(), Vec::with_capacity(arr.len()), arr.iter()));
+                    None
+                }
+                Value::Object(obj) => {
+                    stack.push(Frame::Object(key.cloned(), Map::new(), obj.iter()));
+                    None
+                }
+                v => Some(v.clone()),
+            };
+            if let Some(value) = value {
+                match stack.last_mut() {
+                    Some(parent) => parent.insert(key.cloned(), value),
+                    None => return value,
+                }
+            }
+        }
+
+        let top = stack.last_mut().unwrap();
+        match top.next_child() {
+            Some(child) => next = Some(child),
+            None => {
+                let (key, value) = stack.pop().unwrap().finish();
+                match stack.last_mut() {
+                    Some(parent) => parent.insert(key, value),
+                    None => return value,
+                }
+            }
+        }
+    }
+}
+
+// 删除 key 时保持其他 key 的顺序，开启 preserve_order 时 Map::remove 会把最后一个 key 移到被删除的位置
+#[cfg(feature = "preserve_order")]
+pub(crate) fn remove_key(obj: &mut Map<String, Value>, key: &str) -> Option<Value> {
+    obj.shift_remove(key)
+}
+
+#[cfg(not(feature = "preserve_order"))]
+pub(crate) fn remove_key(obj: &mut Map<String, Value>, key: &str) -> Option<Value> {
+    obj.remove(key)
+}
+
+// 在 index 处插入 key，未开启 preserve_order 时 key 的顺序由 Map 决定，忽略 index
+#[cfg(feature = "preserve_order")]
+pub(crate) fn insert_key_at(obj: &mut Map<String, Value>, index: usize, key: String, v: Value) {
+    let index = index.min(obj.len());
+    obj.shift_insert(index, key, v);
+}
+
+#[cfg(not(feature = "preserve_order"))]
+pub(crate) fn insert_key_at(obj: &mut Map<String, Value>, _index: usize, key: String, v: Value) {
+    obj.insert(key, v);
+}
+
+#[cfg(test)]
+pub(crate) fn nested(depth: usize, leaf: Value) -> Value {
+    let mut v = leaf;
+    for _ in 0..depth {
+        let mut obj = Map::new();
+        obj.insert("a".to_string(), v);
+        v = Value::Object(obj);
+    }
+    v
+}
@@ -1,53 +1,584 @@
 use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
 
+use crate::{json_deep, json_pointer};
+use crate::json_patch::PatchOp;
+
+pub struct DiffOptions {
+    removed: bool,
+    delete_marker: Option<Value>,
+    array_keys: Vec<(Vec<String>, String)>,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions { removed: true, delete_marker: None, array_keys: vec![] }
+    }
+}
+
+impl DiffOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 是否输出 a 中有而 b 中没有的 key，默认输出为 null；关闭时 merge(a, diff(a, b)) 会保留这些 key
+    pub fn removed(mut self, report: bool) -> Self {
+        self.removed = report;
+        self
+    }
+
+    // a 中有而 b 中没有的 key 输出为删除标记而不是 null，配合 json_merge::MergeOptions::delete_marker 使用
+    // 此时 b 中的 null 作为普通值输出
+    pub fn delete_marker(mut self, marker: Value) -> Self {
+        self.delete_marker = Some(marker);
+        self
+    }
+
+    // patch_with 中路径匹配 path 的数组按 key 字段匹配元素，见 diff_array_by_key
+    // path 的格式与 json_merge::MergeOptions::array 相同，如 "/inventory"、"**/items"；先设置的优先
+    pub fn array_key(mut self, path: &str, key: &str) -> Self {
+        self.array_keys.push((json_pointer::parse_pattern(path), key.to_string()));
+        self
+    }
+
+    fn find_array_key(&self, path: &[String]) -> Option<&str> {
+        self.array_keys.iter().find(|(p, _)| json_pointer::matches(p, path)).map(|(_, k)| k.as_str())
+    }
+}
+
+// 返回的差异为 merge patch，merge(a, diff(a, b)) 等于 b，只是 b 中值为 null 的 key 会被删除
+// 没有差异时返回 None
 pub fn diff(a: &Value, b: &Value) -> Option<Value> {
-    match (a, b) {
-        (Value::Object(obj_a), Value::Object(obj_b)) => {
-            let mut result = Map::new();
-
-            for (k, v_b) in obj_b {
-                if let Some(v_a) = obj_a.get(k) {
-                    if v_a != v_b {
-                        if let Some(v_diff) = diff(v_a, v_b) {
-                            result.insert(k.clone(), v_diff);
-                        }
+    diff_with(a, b, &DiffOptions::default())
+}
+
+// 使用显式的栈代替递归，可以处理嵌套很深的文档
+// 开启 preserve_order 时，结果中的 key 按 b 中的顺序排列，删除标记排在最后
+pub fn diff_with(a: &Value, b: &Value, opts: &DiffOptions) -> Option<Value> {
+    let (obj_a, obj_b) = match (a, b) {
+        (Value::Object(obj_a), Value::Object(obj_b)) => (obj_a, obj_b),
+        _ => {
+            return if json_deep::eq(a, b) {
+                None
+            } else {
+                Some(json_deep::clone(b))
+            };
+        }
+    };
+
+    let null = Value::Null;
+    let marker = opts.delete_marker.as_ref().unwrap_or(&null);
+
+    // 每一层对象：a、b、b 的迭代器、已得到的差异、在父对象中的 key
+    let mut stack = vec![(obj_a, obj_b, obj_b.iter(), Map::new(), None)];
+    loop {
+        let (obj_a, obj_b, iter, result, _) = stack.last_mut().unwrap();
+        if let Some((k, v_b)) = iter.next() {
+            match (obj_a.get(k), v_b) {
+                (Some(Value::Object(child_a)), Value::Object(child_b)) => {
+                    stack.push((child_a, child_b, child_b.iter(), Map::new(), Some(k)));
+                }
+                (Some(v_a), v_b) => {
+                    if !json_deep::eq(v_a, v_b) {
+                        result.insert(k.clone(), json_deep::clone(v_b));
                     }
-                } else {
+                }
+                (None, v_b) => {
                     // 如果键在 a 中不存在，加入结果
-                    result.insert(k.clone(), v_b.clone());
+                    result.insert(k.clone(), json_deep::clone(v_b));
                 }
             }
+            continue;
+        }
 
-            if result.is_empty() {
-                None
-            } else {
-                Some(Value::Object(result))
+        if opts.removed {
+            for k in obj_a.keys() {
+                if !obj_b.contains_key(k) {
+                    result.insert(k.clone(), marker.clone());
+                }
             }
         }
-        _ => {
-            if a == b {
-                None
-            } else {
-                Some(b.clone())
+
+        let (_, _, _, result, key) = stack.pop().unwrap();
+        let result = if result.is_empty() {
+            None
+        } else {
+            Some(Value::Object(result))
+        };
+        match (key, stack.last_mut()) {
+            (Some(k), Some((_, _, _, parent, _))) => {
+                if let Some(r) = result {
+                    parent.insert(k.clone(), r);
+                }
             }
+            _ => return result,
         }
     }
 }
 
+// 生成将 a 变为 b 的 RFC 6902 JSON Patch，与 diff 不同，可以表达设置为 null 和数组中的修改
+// 数组按 diff_array 逐个元素比较；a 中删除的值出现在 b 的新位置时生成 move，新增的对象或数组与 a 中没有改变的部分相同时生成 copy
+pub fn patch(a: &Value, b: &Value) -> Vec<PatchOp> {
+    patch_with(a, b, &DiffOptions::default())
+}
+
+// 与 patch 相同，路径匹配 opts 中 array_key 的数组按 diff_array_by_key 比较
+pub fn patch_with(a: &Value, b: &Value, opts: &DiffOptions) -> Vec<PatchOp> {
+    patch_at(a, b, opts, &mut vec![])
+}
+
+// path 为 a、b 在整个文档中的位置，用于匹配 array_key，生成的路径相对于 a
+fn patch_at(a: &Value, b: &Value, opts: &DiffOptions, path: &mut Vec<String>) -> Vec<PatchOp> {
+    let mut p = Patcher { opts, root: path.len(), edits: vec![], unchanged: vec![] };
+    p.diff(a, b, path);
+    p.finish()
+}
+
+enum Edit<'a> {
+    Add(String, &'a Value),
+    Remove(String, &'a Value),
+    Replace(String, &'a Value),
+    // 数组中的修改，不参与 move、copy 的合并
+    Op(PatchOp),
+}
+
+struct Patcher<'a> {
+    opts: &'a DiffOptions,
+    // path 中属于上层文档的部分，不出现在生成的路径中
+    root: usize,
+    edits: Vec<Edit<'a>>,
+    // 没有改变的对象和数组，作为 copy 的来源
+    unchanged: Vec<(String, &'a Value)>,
+}
+
+impl<'a> Patcher<'a> {
+    fn pointer(&self, path: &[String]) -> String {
+        json_pointer::format(&path[self.root..])
+    }
+
+    fn diff(&mut self, a: &'a Value, b: &'a Value, path: &mut Vec<String>) {
+        match (a, b) {
+            (Value::Object(obj_a), Value::Object(obj_b)) => {
+                for (k, v_a) in obj_a {
+                    if !obj_b.contains_key(k) {
+                        path.push(k.clone());
+                        self.edits.push(Edit::Remove(self.pointer(path), v_a));
+                        path.pop();
+                    }
+                }
+                for (k, v_b) in obj_b {
+                    path.push(k.clone());
+                    match obj_a.get(k) {
+                        Some(v_a) => self.diff(v_a, v_b, path),
+                        None => self.edits.push(Edit::Add(self.pointer(path), v_b)),
+                    }
+                    path.pop();
+                }
+            }
+            _ if json_deep::eq(a, b) => {
+                if a.is_object() || a.is_array() {
+                    self.unchanged.push((self.pointer(path), a));
+                }
+            }
+            (Value::Array(arr_a), Value::Array(arr_b)) => {
+                let prefix = self.pointer(path);
+                let key = self.opts.find_array_key(path);
+                for edit in array_edits(arr_a, arr_b, key, self.opts, path) {
+                    self.edits.extend(edit.into_patch(&prefix).into_iter().map(Edit::Op));
+                }
+            }
+            _ => self.edits.push(Edit::Replace(self.pointer(path), b)),
+        }
+    }
+
+    fn finish(self) -> Vec<PatchOp> {
+        // 与新增的值相同的删除合并为 move，每个删除只使用一次，按 hash 索引避免逐个比较
+        let mut removes: HashMap<u64, VecDeque<usize>> = HashMap::new();
+        for (j, edit) in self.edits.iter().enumerate() {
+            if let Edit::Remove(_, old) = edit {
+                removes.entry(json_deep::hash(old)).or_default().push_back(j);
+            }
+        }
+        let mut moved = vec![None; self.edits.len()];
+        let mut moved_away = vec![false; self.edits.len()];
+        if !removes.is_empty() {
+            for (i, edit) in self.edits.iter().enumerate() {
+                if let Edit::Add(_, v) = edit {
+                    let candidates = match removes.get_mut(&json_deep::hash(v)) {
+                        Some(c) => c,
+                        None => continue,
+                    };
+                    let same = |j: &usize| matches!(&self.edits[*j], Edit::Remove(_, old) if json_deep::eq(old, v));
+                    if let Some(p) = candidates.iter().position(same) {
+                        let j = candidates.remove(p).unwrap();
+                        moved[i] = Some(j);
+                        moved_away[j] = true;
+                    }
+                }
+            }
+        }
+
+        // copy 的来源，只在新增了对象或数组时建立索引
+        let mut unchanged: HashMap<u64, Vec<&(String, &Value)>> = HashMap::new();
+        let adds_container = self.edits.iter().any(|e| matches!(e, Edit::Add(_, Value::Object(_) | Value::Array(_))));
+        if adds_container {
+            for u in &self.unchanged {
+                unchanged.entry(json_deep::hash(u.1)).or_default().push(u);
+            }
+        }
+
+        let mut ops = vec![];
+        for (i, edit) in self.edits.iter().enumerate() {
+            match edit {
+                Edit::Op(op) => ops.push(op.clone()),
+                Edit::Remove(..) if moved_away[i] => {}
+                Edit::Remove(path, _) => ops.push(PatchOp::Remove { path: path.clone() }),
+                Edit::Replace(path, v) => ops.push(PatchOp::Replace { path: path.clone(), value: json_deep::clone(v) }),
+                Edit::Add(path, v) => {
+                    if let Some(Edit::Remove(from, _)) = moved[i].map(|j| &self.edits[j]) {
+                        ops.push(PatchOp::Move { from: from.clone(), path: path.clone() });
+                        continue;
+                    }
+                    let copied = match v {
+                        Value::Object(_) | Value::Array(_) => unchanged
+                            .get(&json_deep::hash(v))
+                            .and_then(|c| c.iter().find(|(_, u)| json_deep::eq(u, v))),
+                        _ => None,
+                    };
+                    match copied {
+                        Some((from, _)) => ops.push(PatchOp::Copy { from: from.clone(), path: path.clone() }),
+                        None => ops.push(PatchOp::Add { path: path.clone(), value: json_deep::clone(v) }),
+                    }
+                }
+            }
+        }
+        ops
+    }
+}
+
+// 数组的一个修改，按顺序应用，index 为应用前面的修改之后的位置，与 RFC 6902 相同
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrayEdit {
+    Insert { index: usize, value: Value },
+    Delete { index: usize, value: Value },
+    // 与 JSON Patch 的 move 相同：先从 from 删除，再插入到 to
+    Move { from: usize, to: usize },
+    // 元素内部的修改，ops 中的路径相对于该元素
+    Modify { index: usize, ops: Vec<PatchOp> },
+    // 两个数组差别太大（编辑距离超过 MAX_EDIT_DISTANCE）时整体替换，只会单独出现
+    Replace { values: Vec<Value> },
+}
+
+impl ArrayEdit {
+    // 转换为 JSON Patch，prefix 为数组的 JSON Pointer
+    pub fn into_patch(self, prefix: &str) -> Vec<PatchOp> {
+        match self {
+            ArrayEdit::Insert { index, value } => vec![PatchOp::Add { path: format!("{}/{}", prefix, index), value }],
+            ArrayEdit::Delete { index, .. } => vec![PatchOp::Remove { path: format!("{}/{}", prefix, index) }],
+            ArrayEdit::Move { from, to } => {
+                vec![PatchOp::Move { from: format!("{}/{}", prefix, from), path: format!("{}/{}", prefix, to) }]
+            }
+            ArrayEdit::Modify { index, ops } => {
+                let prefix = format!("{}/{}", prefix, index);
+                ops.into_iter().map(|op| op.with_prefix(&prefix)).collect()
+            }
+            ArrayEdit::Replace { values } => vec![PatchOp::Replace { path: prefix.to_string(), value: Value::Array(values) }],
+        }
+    }
+}
+
+// 用 Myers 算法求 a、b 的最长公共子序列，得到插入和删除，再把相同的删除和插入合并为移动，
+// 同一处连续的删除和插入中类型相同的对象或数组按顺序配对，合并为元素内部的修改
+// 编辑距离超过 MAX_EDIT_DISTANCE 时只返回一个 Replace
+pub fn diff_array(a: &[Value], b: &[Value]) -> Vec<ArrayEdit> {
+    array_edits(a, b, None, &DiffOptions::default(), &mut vec![])
+}
+
+// 按 key 字段（如 "id"）匹配元素，适用于对象数组：key 相同的元素比较内部的修改，顺序改变的生成移动，
+// 其余的为新增和删除；没有 key 字段的元素只会被新增或删除，key 重复时按顺序匹配
+pub fn diff_array_by_key(a: &[Value], b: &[Value], key: &str) -> Vec<ArrayEdit> {
+    array_edits(a, b, Some(key), &DiffOptions::default(), &mut vec![])
+}
+
+fn array_edits(a: &[Value], b: &[Value], key: Option<&str>, opts: &DiffOptions, path: &mut Vec<String>) -> Vec<ArrayEdit> {
+    // a 中元素对应的 b 中位置，以及反向的对应
+    let mut target = vec![None; a.len()];
+    let mut source = vec![None; b.len()];
+    let steps = match key {
+        Some(key) => match_by_key(a, b, key, &mut target, &mut source),
+        None => match_by_value(a, b, &mut target, &mut source),
+    };
+    let steps = match steps {
+        Some(steps) => steps,
+        None => return vec![ArrayEdit::Replace { values: b.iter().map(json_deep::clone).collect() }],
+    };
+
+    // 模拟修改过程，cur 中为当前数组中各元素在 a 中的位置（新插入的为 None）以及是否等待移走
+    // 等待移动到后面的元素先留在原处，它们最终都会被移走，不影响其他元素的相对顺序
+    let mut cur: Vec<(Option<usize>, bool)> = (0..a.len()).map(|i| (Some(i), false)).collect();
+    let mut pos = 0;
+    let mut edits = vec![];
+    let mut modify = |i: usize, j: usize, index: usize, edits: &mut Vec<ArrayEdit>| {
+        if !json_deep::eq(&a[i], &b[j]) {
+            path.push(j.to_string());
+            edits.push(ArrayEdit::Modify { index, ops: patch_at(&a[i], &b[j], opts, path) });
+            path.pop();
+        }
+    };
+    for s in steps {
+        match s {
+            Step::Keep(i, j) => {
+                // 按值匹配时保留的元素一定相同
+                if target[i].is_some() {
+                    modify(i, j, pos, &mut edits);
+                }
+                pos += 1;
+            }
+            Step::Delete(i) => {
+                if cur.get(pos).map(|e| e.0) != Some(Some(i)) {
+                    // 已经被移动到前面
+                    continue;
+                }
+                if target[i].is_some() {
+                    cur[pos].1 = true;
+                    pos += 1;
+                } else {
+                    cur.remove(pos);
+                    edits.push(ArrayEdit::Delete { index: pos, value: json_deep::clone(&a[i]) });
+                }
+            }
+            Step::Insert(j) => {
+                let i = match source[j] {
+                    Some(i) => i,
+                    None => {
+                        cur.insert(pos, (None, false));
+                        edits.push(ArrayEdit::Insert { index: pos, value: json_deep::clone(&b[j]) });
+                        pos += 1;
+                        continue;
+                    }
+                };
+                let p = cur.iter().position(|e| e.0 == Some(i)).unwrap();
+                let index = if p < pos && cur[p + 1..pos].iter().all(|e| e.1) {
+                    cur[p].1 = false;
+                    p
+                } else if p < pos {
+                    cur.remove(p);
+                    cur.insert(pos - 1, (Some(i), false));
+                    edits.push(ArrayEdit::Move { from: p, to: pos - 1 });
+                    pos - 1
+                } else {
+                    cur.remove(p);
+                    cur.insert(pos, (Some(i), false));
+                    edits.push(ArrayEdit::Move { from: p, to: pos });
+                    pos += 1;
+                    pos - 1
+                };
+                modify(i, j, index, &mut edits);
+            }
+        }
+    }
+    edits
+}
+
+fn match_by_value(a: &[Value], b: &[Value], target: &mut [Option<usize>], source: &mut [Option<usize>]) -> Option<Vec<Step>> {
+    // 先比较 hash，不同的元素大多不需要深度比较
+    let ha: Vec<u64> = a.iter().map(json_deep::hash).collect();
+    let hb: Vec<u64> = b.iter().map(json_deep::hash).collect();
+    let eq = |i: usize, j: usize| ha[i] == hb[j] && json_deep::eq(&a[i], &b[j]);
+    let steps = edit_script(a.len(), b.len(), eq)?;
+
+    let deletes: Vec<usize> = steps.iter().filter_map(|s| if let Step::Delete(i) = s { Some(*i) } else { None }).collect();
+    for s in &steps {
+        if let Step::Insert(j) = *s {
+            if let Some(&i) = deletes.iter().find(|&&i| target[i].is_none() && eq(i, j)) {
+                target[i] = Some(j);
+                source[j] = Some(i);
+            }
+        }
+    }
+    for hunk in steps.split(|s| matches!(s, Step::Keep(..))) {
+        let dels: Vec<usize> = hunk
+            .iter()
+            .filter_map(|s| match *s {
+                Step::Delete(i) if target[i].is_none() => Some(i),
+                _ => None,
+            })
+            .collect();
+        let ins: Vec<usize> = hunk
+            .iter()
+            .filter_map(|s| match *s {
+                Step::Insert(j) if source[j].is_none() => Some(j),
+                _ => None,
+            })
+            .collect();
+        for (i, j) in dels.into_iter().zip(ins) {
+            let same_kind = matches!((&a[i], &b[j]), (Value::Object(_), Value::Object(_)) | (Value::Array(_), Value::Array(_)));
+            if same_kind {
+                target[i] = Some(j);
+                source[j] = Some(i);
+            }
+        }
+    }
+    Some(steps)
+}
+
+fn match_by_key(a: &[Value], b: &[Value], key: &str, target: &mut [Option<usize>], source: &mut [Option<usize>]) -> Option<Vec<Step>> {
+    // 以 key 的 json 文本作为索引，数字 1 和字符串 "1" 是不同的 key
+    let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, v) in a.iter().enumerate().rev() {
+        if let Some(id) = v.get(key) {
+            index.entry(id.to_string()).or_default().push(i);
+        }
+    }
+    for (j, v) in b.iter().enumerate() {
+        if let Some(i) = v.get(key).and_then(|id| index.get_mut(&id.to_string())).and_then(|l| l.pop()) {
+            target[i] = Some(j);
+            source[j] = Some(i);
+        }
+    }
+    edit_script(a.len(), b.len(), |i, j| target[i] == Some(j))
+}
+
+#[derive(Clone, Copy)]
+enum Step {
+    Keep(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+// 编辑距离超过这个值时不再逐个元素比较，整体替换数组
+// Myers 算法的耗时约为编辑距离的平方，两个很大且几乎完全不同的数组逐个比较没有意义
+const MAX_EDIT_DISTANCE: usize = 4096;
+
+// 求从 a 到 b 的最短编辑序列，n、m 为 a、b 的长度，编辑距离超过 MAX_EDIT_DISTANCE 时返回 None
+fn edit_script(n: usize, m: usize, eq: impl Fn(usize, usize) -> bool) -> Option<Vec<Step>> {
+    let offset = (n + m) / 2 + 2;
+    let mut myers = Myers { eq, vf: vec![0; 2 * offset + 1], vb: vec![0; 2 * offset + 1], offset, steps: vec![] };
+    myers.diff(0, n, 0, m, Some(MAX_EDIT_DISTANCE))?;
+
+    // 同一处连续的修改中删除排在插入之前
+    let mut steps = myers.steps;
+    for hunk in steps.split_mut(|s| matches!(s, Step::Keep(..))) {
+        hunk.sort_by_key(|s| matches!(s, Step::Insert(_)));
+    }
+    Some(steps)
+}
+
+// 线性空间的 Myers O(ND) 差分算法：同时从两端搜索，找到最短路径中间的 snake 后分别递归求两边
+// 内存只与 n + m 有关，vf、vb 为正向、反向搜索时每条对角线 k 上走得最远的 x
+struct Myers<F> {
+    eq: F,
+    vf: Vec<isize>,
+    vb: Vec<isize>,
+    offset: usize,
+    steps: Vec<Step>,
+}
+
+impl<F: Fn(usize, usize) -> bool> Myers<F> {
+    // 求 a[a0..a1] 到 b[b0..b1] 的编辑序列，limit 为允许的最大编辑距离，超过时返回 None
+    fn diff(&mut self, mut a0: usize, mut a1: usize, mut b0: usize, mut b1: usize, limit: Option<usize>) -> Option<()> {
+        // 先去掉相同的开头和结尾，剩下的部分两端不同
+        while a0 < a1 && b0 < b1 && (self.eq)(a0, b0) {
+            self.steps.push(Step::Keep(a0, b0));
+            a0 += 1;
+            b0 += 1;
+        }
+        let mut suffix = 0;
+        while a0 < a1 && b0 < b1 && (self.eq)(a1 - 1, b1 - 1) {
+            a1 -= 1;
+            b1 -= 1;
+            suffix += 1;
+        }
+
+        if a0 == a1 {
+            self.steps.extend((b0..b1).map(Step::Insert));
+        } else if b0 == b1 {
+            self.steps.extend((a0..a1).map(Step::Delete));
+        } else {
+            let (x, y) = self.middle_snake(a0, a1, b0, b1, limit)?;
+            self.diff(a0, x, b0, y, None)?;
+            self.diff(x, a1, y, b1, None)?;
+        }
+        self.steps.extend((0..suffix).map(|i| Step::Keep(a1 + i, b1 + i)));
+        Some(())
+    }
+
+    // 返回最短路径中间 snake 的起点，两个序列都不为空且首尾不同
+    fn middle_snake(&mut self, a0: usize, a1: usize, b0: usize, b1: usize, limit: Option<usize>) -> Option<(usize, usize)> {
+        let (n, m) = ((a1 - a0) as isize, (b1 - b0) as isize);
+        let delta = n - m;
+        let odd = delta % 2 != 0;
+        let offset = self.offset as isize;
+        let at = move |k: isize| (offset + k) as usize;
+        self.vf[at(1)] = 0;
+        self.vb[at(1)] = 0;
+
+        for d in 0..=(n + m + 1) / 2 {
+            // 编辑距离至少为 2d - 1
+            if limit.is_some_and(|l| 2 * d - 1 > l as isize) {
+                return None;
+            }
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x = if k == -d || (k != d && self.vf[at(k - 1)] < self.vf[at(k + 1)]) {
+                    self.vf[at(k + 1)]
+                } else {
+                    self.vf[at(k - 1)] + 1
+                };
+                let (x0, y0) = (x, x - k);
+                let mut y = y0;
+                while x < n && y >= 0 && y < m && (self.eq)(a0 + x as usize, b0 + y as usize) {
+                    x += 1;
+                    y += 1;
+                }
+                self.vf[at(k)] = x;
+                if odd && (k - delta).abs() < d && x + self.vb[at(delta - k)] >= n {
+                    return Some((a0 + x0 as usize, b0 + y0 as usize));
+                }
+            }
+            // 在倒序的 a、b 上做同样的搜索
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x = if k == -d || (k != d && self.vb[at(k - 1)] < self.vb[at(k + 1)]) {
+                    self.vb[at(k + 1)]
+                } else {
+                    self.vb[at(k - 1)] + 1
+                };
+                let mut y = x - k;
+                while x < n && y >= 0 && y < m && (self.eq)(a1 - 1 - x as usize, b1 - 1 - y as usize) {
+                    x += 1;
+                    y += 1;
+                }
+                self.vb[at(k)] = x;
+                if !odd && (k - delta).abs() <= d && x + self.vf[at(delta - k)] >= n {
+                    return Some((a1 - x as usize, b1 - y as usize));
+                }
+            }
+        }
+        unreachable!("myers middle snake")
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::json_merge::merge;
     use serde_json::json;
 
-    // 验证 merge(a, diff(a, b)) == merge(a, b) 的辅助函数
+    // 去掉对象中值为 null 的 key，数组中的 null 保留
+    fn strip_nulls(v: &Value) -> Value {
+        match v {
+            Value::Object(obj) => {
+                Value::Object(obj.iter().filter(|(_, v)| !v.is_null()).map(|(k, v)| (k.clone(), strip_nulls(v))).collect())
+            }
+            v => v.clone(),
+        }
+    }
+
+    // 验证 merge(a, diff(a, b)) == b 的辅助函数，b 中值为 null 的 key 会被 merge 删除
     fn verify_diff_merge_equality(a: &Value, b: &Value) {
         let mut a1 = a.clone();
-        let mut a2 = a.clone();
         if let Some(c) = diff(a, b) {
-            merge(&mut a1, b.clone());
-            merge(&mut a2, c);
-            assert_eq!(a1, a2, "合并结果不一致: merge(a, b) != merge(a, diff(a, b))");
+            merge(&mut a1, c);
+            assert_eq!(a1, strip_nulls(b), "合并结果不一致: merge(a, diff(a, b)) != b");
         } else {
             assert_eq!(a, b, "diff return None but a != b");
         }
@@ -81,7 +612,8 @@ mod test {
             "title": "This is another title",
             "person": {
                 "firstName": "Jane",
-                "lastName": null
+                "lastName": null,
+                "shortName": null
             },
             "cities": ["colombo"]
         });
@@ -340,6 +872,7 @@ mod test {
         merge(&mut a2, c);
 
         assert_eq!(a1, a2, "复杂场景合并结果不一致");
+        verify_diff_merge_equality(&a, &b);
     }
 
     #[test]
@@ -457,4 +990,318 @@ mod test {
         assert_eq!(c, None, "两个空数组应返回 None");
         verify_diff_merge_equality(&a, &b);
     }
-} 
+
+    #[test]
+    fn test_diff_with_delete_marker() {
+        // 测试删除标记模式，diff 结果可以还原出 b，包括值为 null 的字段
+        use crate::json_merge::{merge_with, MergeOptions};
+
+        let a = json!({
+            "keep": "value",
+            "removed": "gone",
+            "to_null": "value",
+            "nested": {"removed": 1, "keep": 2}
+        });
+        let b = json!({
+            "keep": "value",
+            "to_null": null,
+            "nested": {"keep": 2},
+            "added": {"x": null}
+        });
+
+        let marker = json!({"$delete": true});
+        let c = diff_with(&a, &b, &DiffOptions::new().delete_marker(marker.clone())).unwrap();
+        let expected_diff = json!({
+            "removed": {"$delete": true},
+            "to_null": null,
+            "nested": {"removed": {"$delete": true}},
+            "added": {"x": null}
+        });
+        assert_eq!(c, expected_diff);
+
+        let mut a1 = a.clone();
+        merge_with(&mut a1, c, &MergeOptions::new().delete_marker(marker)).unwrap();
+        assert_eq!(a1, b, "删除标记模式下 merge(a, diff(a, b)) 应等于 b");
+    }
+
+    #[test]
+    fn test_diff_deeply_nested() {
+        // 测试嵌套 10 万层的文档不会栈溢出
+        use crate::json_deep::{self, nested};
+
+        // json! 会对表达式做递归的序列化，这里手动构造
+        let leaf = |v: Value| {
+            let mut obj = Map::new();
+            obj.insert("change".to_string(), json!(2));
+            obj.insert("add".to_string(), nested(100_000, json!(1)));
+            if !v.is_null() {
+                obj.insert("keep".to_string(), v);
+            }
+            Value::Object(obj)
+        };
+
+        let a = nested(100_000, json!({"keep": 1, "change": 1}));
+        let b = nested(100_000, leaf(json!(1)));
+
+        let c = diff(&a, &b).unwrap();
+        let expected = nested(100_000, leaf(Value::Null));
+        assert!(json_deep::eq(&c, &expected), "深层 diff 结果不正确");
+        assert_eq!(diff(&a, &a), None);
+
+        for v in [a, b, c, expected] {
+            json_deep::drop(v);
+        }
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[test]
+    fn test_diff_preserve_order() {
+        let a: Value = serde_json::from_str(r#"{"z": 1, "old": 1, "m": {"y": 1, "b": 2}}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"z": 2, "m": {"b": 3, "y": 2}, "new": 1}"#).unwrap();
+        let opts = DiffOptions::new().delete_marker(Value::Null);
+        let d = diff_with(&a, &b, &opts).unwrap();
+        assert_eq!(d.to_string(), r#"{"z":2,"m":{"b":3,"y":2},"new":1,"old":null}"#);
+    }
+
+    #[test]
+    fn test_patch() {
+        let a = json!({"a": 1, "b": {"c": 2, "d": [1, 2]}, "e": "x", "f": {"g": 1}, "h": 1});
+        let b = json!({"a": null, "b": {"c": 3, "d": [1, 2]}, "moved": "x", "f2": {"g": 1}, "d2": [1, 2], "h": 1});
+        let mut ops = patch(&a, &b);
+        ops.sort_by(|x, y| x.path().cmp(y.path()));
+        let expected = vec![
+            PatchOp::Replace { path: "/a".to_string(), value: Value::Null },
+            PatchOp::Replace { path: "/b/c".to_string(), value: json!(3) },
+            PatchOp::Copy { from: "/b/d".to_string(), path: "/d2".to_string() },
+            PatchOp::Move { from: "/f".to_string(), path: "/f2".to_string() },
+            PatchOp::Move { from: "/e".to_string(), path: "/moved".to_string() },
+        ];
+        assert_eq!(ops, expected);
+
+        assert_eq!(patch(&a, &a), vec![]);
+        assert_eq!(patch(&json!([1, 2]), &json!([1])), vec![PatchOp::Remove { path: "/1".to_string() }]);
+        assert_eq!(
+            patch(&json!({"a/b": 1, "m~n": 1}), &json!({"m~n": 2})),
+            vec![
+                PatchOp::Remove { path: "/a~1b".to_string() },
+                PatchOp::Replace { path: "/m~0n".to_string(), value: json!(2) },
+            ]
+        );
+
+        // 序列化为 RFC 6902 格式
+        let ops = patch(&json!({"a": 1}), &json!({"b": null}));
+        assert_eq!(
+            serde_json::to_value(&ops).unwrap(),
+            json!([{"op": "remove", "path": "/a"}, {"op": "add", "path": "/b", "value": null}])
+        );
+    }
+
+    #[test]
+    fn test_patch_many_keys() {
+        // 所有 key 都被替换时合并 move 和 copy 不能逐个比较
+        let n = 5000;
+        let mut a = Value::Object((0..n).map(|i| (format!("old{}", i), json!({"v": i}))).collect());
+        let mut b = Value::Object((0..n).map(|i| (format!("new{}", i), json!({"v": i + n}))).collect());
+        a["same"] = json!([1, 2]);
+        b["same"] = json!([1, 2]);
+        // 一个删除的值移动到新 key，一个新 key 复制未改变的值
+        b["moved"] = json!({"v": 1});
+        b["copied"] = json!([1, 2]);
+
+        let t = std::time::Instant::now();
+        let ops = patch(&a, &b);
+        assert!(t.elapsed() < std::time::Duration::from_secs(2), "patch 耗时 {:?}", t.elapsed());
+        assert_eq!(ops.len(), 2 * n + 1);
+        assert!(ops.contains(&PatchOp::Move { from: "/old1".to_string(), path: "/moved".to_string() }));
+        assert!(ops.contains(&PatchOp::Copy { from: "/same".to_string(), path: "/copied".to_string() }));
+
+        let mut a1 = a.clone();
+        crate::json_patch::apply(&mut a1, &ops).unwrap();
+        assert_eq!(a1, b);
+    }
+
+    #[test]
+    fn test_patch_apply() {
+        // apply(a, patch(a, b)) == b
+        let cases = [
+            (json!({"a": 1, "b": {"c": [1, 2]}, "d": "x"}), json!({"a": null, "b": {"c": [2]}, "e": "x", "f": {"c": [2]}})),
+            (json!({"a": {"b": 1}}), json!({"c": {"b": 1}, "a": 2})),
+            (json!([1, 2]), json!({"a": [1, 2]})),
+            (json!({"a": 1}), json!(null)),
+        ];
+        for (a, b) in cases {
+            let ops = patch(&a, &b);
+            let mut a1 = a.clone();
+            crate::json_patch::apply(&mut a1, &ops).unwrap();
+            assert_eq!(a1, b, "patch({}, {}) = {:?}", a, b, ops);
+        }
+    }
+
+    #[test]
+    fn test_diff_removed_keys() {
+        // a 中有而 b 中没有的 key 输出为 null
+        let a = json!({
+            "keep": 1,
+            "removed": "gone",
+            "nested": {"removed": {"x": 1}, "keep": 2},
+            "obj_to_scalar": {"x": 1}
+        });
+        let b = json!({
+            "keep": 1,
+            "nested": {"keep": 2},
+            "obj_to_scalar": 1,
+            "added": {"y": 1}
+        });
+
+        let c = diff(&a, &b).unwrap();
+        let expected_diff = json!({
+            "removed": null,
+            "nested": {"removed": null},
+            "obj_to_scalar": 1,
+            "added": {"y": 1}
+        });
+        assert_eq!(c, expected_diff);
+
+        let mut a1 = a.clone();
+        merge(&mut a1, c);
+        assert_eq!(a1, b, "merge(a, diff(a, b)) 应等于 b");
+        verify_diff_merge_equality(&b, &a);
+
+        // 关闭后不输出删除的 key
+        let c = diff_with(&a, &b, &DiffOptions::new().removed(false)).unwrap();
+        assert_eq!(c, json!({"obj_to_scalar": 1, "added": {"y": 1}}));
+        assert_eq!(diff_with(&a, &json!({"keep": 1}), &DiffOptions::new().removed(false)), None);
+    }
+
+    // 验证 diff_array 的结果转换为 JSON Patch 后可以把 a 变为 b
+    fn verify_array_edits(a: &Value, b: &Value) -> Vec<ArrayEdit> {
+        let edits = diff_array(a.as_array().unwrap(), b.as_array().unwrap());
+        let ops: Vec<PatchOp> = edits.iter().cloned().flat_map(|e| e.into_patch("")).collect();
+        let mut a1 = a.clone();
+        crate::json_patch::apply(&mut a1, &ops).unwrap();
+        assert_eq!(&a1, b, "diff_array({}, {}) = {:?}", a, b, edits);
+        edits
+    }
+
+    #[test]
+    fn test_diff_array() {
+        // 在开头插入一个元素只产生一个修改
+        let a = json!((0..1000).map(|i| json!({"id": i})).collect::<Vec<_>>());
+        let mut b = a.clone();
+        b.as_array_mut().unwrap().insert(0, json!({"id": -1}));
+        let edits = verify_array_edits(&a, &b);
+        assert_eq!(edits, vec![ArrayEdit::Insert { index: 0, value: json!({"id": -1}) }]);
+
+        let edits = verify_array_edits(&json!([1, 2, 3]), &json!([1, 3]));
+        assert_eq!(edits, vec![ArrayEdit::Delete { index: 1, value: json!(2) }]);
+
+        // 相同的元素换了位置时生成 move
+        let edits = verify_array_edits(&json!(["a", "b", "c", "d"]), &json!(["d", "a", "b", "c"]));
+        assert_eq!(edits, vec![ArrayEdit::Move { from: 3, to: 0 }]);
+        let edits = verify_array_edits(&json!(["a", "b", "c", "d"]), &json!(["b", "c", "d", "a"]));
+        assert_eq!(edits, vec![ArrayEdit::Move { from: 0, to: 3 }]);
+
+        // 修改的对象元素递归比较
+        let edits = verify_array_edits(
+            &json!([{"id": 1, "v": 1}, {"id": 2, "v": 2}, 3]),
+            &json!([{"id": 1, "v": 1}, {"id": 2, "v": 20}, 3]),
+        );
+        assert_eq!(
+            edits,
+            vec![ArrayEdit::Modify { index: 1, ops: vec![PatchOp::Replace { path: "/v".to_string(), value: json!(20) }] }]
+        );
+        assert_eq!(
+            patch(&json!({"list": [{"v": [1, 2]}]}), &json!({"list": [{"v": [2]}]})),
+            vec![PatchOp::Remove { path: "/list/0/v/0".to_string() }]
+        );
+
+        // 混合的修改
+        let cases = [
+            (json!([]), json!([1, 2])),
+            (json!([1, 2]), json!([])),
+            (json!([1, 2, 3, 4, 5]), json!([5, 4, 3, 2, 1])),
+            (json!(["a", "b", "c", "a", "b", "b", "a"]), json!(["c", "b", "a", "b", "a", "c"])),
+            (json!([{"a": 1}, {"b": 1}, [1], "x"]), json!([[2], "y", {"b": 2}, {"a": 1}, {"c": 1}])),
+            (json!([1, {"a": [1, 2, {"b": 1}]}, 3]), json!([{"a": [2, {"b": 2}, 1]}, 3, 1, 1])),
+        ];
+        for (a, b) in cases {
+            verify_array_edits(&a, &b);
+            verify_array_edits(&b, &a);
+        }
+    }
+
+    #[test]
+    fn test_diff_array_large() {
+        // 很大的数组中少量修改仍然逐个元素比较
+        let a = json!((0..20000).collect::<Vec<_>>());
+        let b = json!((0..20000).map(|i| if i % 1000 == 0 { -i - 1 } else { i }).collect::<Vec<_>>());
+        let edits = verify_array_edits(&a, &b);
+        assert_eq!(edits.len(), 40);
+
+        // 几乎完全不同时整体替换
+        let b = json!((0..20000).map(|i| if i % 100 == 0 { i } else { -i }).collect::<Vec<_>>());
+        let edits = verify_array_edits(&a, &b);
+        assert!(matches!(edits.as_slice(), [ArrayEdit::Replace { .. }]), "应整体替换");
+        let ops = patch(&json!({"list": a.clone()}), &json!({"list": b.clone()}));
+        assert_eq!(ops, vec![PatchOp::Replace { path: "/list".to_string(), value: b }]);
+    }
+
+    #[test]
+    fn test_diff_array_by_key() {
+        let a = json!([
+            {"id": 1, "name": "bolt", "qty": 10},
+            {"id": 2, "name": "nut", "qty": 5},
+            {"id": 3, "name": "gear", "qty": 1},
+            {"id": 4, "name": "cog", "qty": 7}
+        ]);
+        let b = json!([
+            {"id": 0, "name": "washer", "qty": 100},
+            {"id": 1, "name": "bolt", "qty": 10},
+            {"id": 3, "name": "gear", "qty": 2},
+            {"id": 4, "name": "cog", "qty": 7},
+            {"id": 2, "name": "nut", "qty": 5}
+        ]);
+        let edits = diff_array_by_key(a.as_array().unwrap(), b.as_array().unwrap(), "id");
+        let expected = vec![
+            ArrayEdit::Insert { index: 0, value: json!({"id": 0, "name": "washer", "qty": 100}) },
+            ArrayEdit::Modify { index: 3, ops: vec![PatchOp::Replace { path: "/qty".to_string(), value: json!(2) }] },
+            ArrayEdit::Move { from: 2, to: 4 },
+        ];
+        assert_eq!(edits, expected);
+
+        let ops: Vec<PatchOp> = edits.into_iter().flat_map(|e| e.into_patch("")).collect();
+        let mut a1 = a.clone();
+        crate::json_patch::apply(&mut a1, &ops).unwrap();
+        assert_eq!(a1, b);
+    }
+
+    #[test]
+    fn test_patch_with_array_key() {
+        let a = json!({
+            "inventory": [{"sku": "a", "qty": 1, "tags": [{"k": "x", "v": 1}]}, {"sku": "b", "qty": 2}],
+            "other": [{"sku": "a"}, {"sku": "b"}]
+        });
+        let b = json!({
+            "inventory": [{"sku": "c", "qty": 3}, {"sku": "b", "qty": 2}, {"sku": "a", "qty": 1, "tags": [{"k": "y"}, {"k": "x", "v": 2}]}],
+            "other": [{"sku": "b"}, {"sku": "a"}]
+        });
+        let opts = DiffOptions::new().array_key("/inventory", "sku").array_key("/inventory/*/tags", "k");
+        let ops = patch_with(&a, &b, &opts);
+        let expected = vec![
+            PatchOp::Add { path: "/inventory/1".to_string(), value: json!({"sku": "c", "qty": 3}) },
+            PatchOp::Move { from: "/inventory/0".to_string(), path: "/inventory/2".to_string() },
+            PatchOp::Add { path: "/inventory/2/tags/0".to_string(), value: json!({"k": "y"}) },
+            PatchOp::Replace { path: "/inventory/2/tags/1/v".to_string(), value: json!(2) },
+            PatchOp::Move { from: "/other/1".to_string(), path: "/other/0".to_string() },
+        ];
+        assert_eq!(ops, expected);
+
+        let mut a1 = a.clone();
+        crate::json_patch::apply(&mut a1, &ops).unwrap();
+        assert_eq!(a1, b);
+
+        // 元素没有 key 字段时只能新增或删除
+        let edits = diff_array_by_key(&[json!(1), json!({"id": 1})], &[json!({"id": 1}), json!(1)], "id");
+        assert_eq!(edits, vec![ArrayEdit::Delete { index: 0, value: json!(1) }, ArrayEdit::Insert { index: 1, value: json!(1) }]);
+    }
+}
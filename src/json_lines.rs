@@ -0,0 +1,205 @@
+use anyhow::{anyhow, Context};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+use crate::{json_merge, json_pointer};
+
+// 按 key 字段匹配记录，用 overlay 中的记录逐条 json_merge::merge 到 base 中
+pub struct LinesOptions {
+    key: String,
+    tombstone: Option<(String, Value)>,
+}
+
+impl LinesOptions {
+    // key 为记录的 id 字段，支持 JSON Pointer 或 '.' 分隔的路径，如 "id"、"meta.id"
+    // 默认 overlay 中 "_deleted" 为 true 的记录删除 base 中的整条记录
+    pub fn new(key: &str) -> Self {
+        LinesOptions { key: key.to_string(), tombstone: Some(("_deleted".to_string(), Value::Bool(true))) }
+    }
+
+    pub fn tombstone(mut self, field: &str, value: Value) -> Self {
+        self.tombstone = Some((field.to_string(), value));
+        self
+    }
+
+    pub fn no_tombstone(mut self) -> Self {
+        self.tombstone = None;
+        self
+    }
+
+    fn is_tombstone(&self, record: &Value) -> bool {
+        match &self.tombstone {
+            Some((field, value)) => record.get(field) == Some(value),
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LinesStats {
+    // base 中没有对应 overlay 记录、原样输出的记录数
+    pub kept: usize,
+    pub merged: usize,
+    pub deleted: usize,
+    // 只在 overlay 中出现、追加到最后的记录数
+    pub added: usize,
+}
+
+// 合并 NDJSON 记录流：overlay 全部读入内存，base 逐行读取并写出，输出顺序与 base 相同，新增记录按 overlay 的顺序追加
+// 空行忽略，记录缺少 key 字段或 overlay 中 key 重复时返回错误
+pub fn merge<B, P, W>(base: B, overlay: P, out: W, opts: &LinesOptions) -> anyhow::Result<LinesStats>
+where
+    B: BufRead,
+    P: BufRead,
+    W: Write,
+{
+    let key_pointer = json_pointer::format(&json_pointer::parse_path(&opts.key)?);
+
+    let mut patches = vec![];
+    let mut index = HashMap::new();
+    for (n, line) in overlay.lines().enumerate() {
+        let line = line.context(format!("read overlay line {}", n + 1))?;
+        let record = match parse_line(&line).context(format!("overlay line {}", n + 1))? {
+            Some(v) => v,
+            None => continue,
+        };
+        let key = record_key(&record, &key_pointer).context(format!("overlay line {}", n + 1))?;
+        if index.insert(key.clone(), patches.len()).is_some() {
+            return Err(anyhow!("duplicate key {} in overlay line {}", key, n + 1));
+        }
+        patches.push(record);
+    }
+
+    let mut applied = vec![false; patches.len()];
+    let mut stats = LinesStats::default();
+    let mut out = BufWriter::new(out);
+    for (n, line) in base.lines().enumerate() {
+        let line = line.context(format!("read base line {}", n + 1))?;
+        let mut record = match parse_line(&line).context(format!("base line {}", n + 1))? {
+            Some(v) => v,
+            None => continue,
+        };
+        let key = record_key(&record, &key_pointer).context(format!("base line {}", n + 1))?;
+
+        // base 中 key 重复时 overlay 对每条都生效
+        let i = index.get(&key).copied();
+        match i.map(|i| &patches[i]) {
+            None => {
+                stats.kept += 1;
+                writeln!(out, "{}", line.trim())?;
+            }
+            Some(patch) if opts.is_tombstone(patch) => stats.deleted += 1,
+            Some(patch) => {
+                stats.merged += 1;
+                json_merge::merge(&mut record, patch.clone());
+                writeln!(out, "{}", record)?;
+            }
+        }
+        if let Some(i) = i {
+            applied[i] = true;
+        }
+    }
+
+    // 没有匹配到的 overlay 记录，墓碑记录直接丢弃
+    for (record, applied) in patches.into_iter().zip(applied) {
+        if applied || opts.is_tombstone(&record) {
+            continue;
+        }
+        stats.added += 1;
+        writeln!(out, "{}", record)?;
+    }
+
+    out.flush()?;
+    Ok(stats)
+}
+
+pub fn merge_files(base: &str, overlay: &str, out: &str, opts: &LinesOptions) -> anyhow::Result<LinesStats> {
+    let b = std::fs::File::open(base).context(format!("open base {}", base))?;
+    let p = std::fs::File::open(overlay).context(format!("open overlay {}", overlay))?;
+    let o = std::fs::File::create(out).context(format!("create {}", out))?;
+    merge(BufReader::new(b), BufReader::new(p), o, opts).context(format!("merge {} and {} into {}", base, overlay, out))
+}
+
+fn parse_line(line: &str) -> anyhow::Result<Option<Value>> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_str(line).map(Some).map_err(|e| anyhow!("decode record FAILED! {}", e))
+}
+
+// 以 key 的 json 文本作为索引，数字 1 和字符串 "1" 是不同的 key
+fn record_key(record: &Value, pointer: &str) -> anyhow::Result<String> {
+    match record.pointer(pointer) {
+        Some(Value::Null) | None => Err(anyhow!("record has no key {}", pointer)),
+        Some(v) => Ok(v.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn merge_str(base: &str, overlay: &str, opts: &LinesOptions) -> anyhow::Result<(String, LinesStats)> {
+        let mut out = vec![];
+        let stats = merge(base.as_bytes(), overlay.as_bytes(), &mut out, opts)?;
+        Ok((String::from_utf8(out).unwrap(), stats))
+    }
+
+    #[test]
+    fn test_merge_lines() {
+        let base = r#"{"id": 1, "name": "a", "qty": 1}
+{"id": 2, "name": "b", "qty": 2}
+
+{"id": 3, "name": "c", "qty": 3}
+{"id": "1", "name": "string id"}
+"#;
+        let overlay = r#"{"id": 5, "name": "e"}
+{"id": 2, "qty": 20, "name": null}
+{"id": 3, "_deleted": true}
+{"id": 9, "_deleted": true}
+{"id": 4, "name": "d"}
+"#;
+        let (out, stats) = merge_str(base, overlay, &LinesOptions::new("id")).unwrap();
+        assert_eq!(
+            out,
+            r#"{"id": 1, "name": "a", "qty": 1}
+{"id":2,"qty":20}
+{"id": "1", "name": "string id"}
+{"id":5,"name":"e"}
+{"id":4,"name":"d"}
+"#
+        );
+        assert_eq!(stats, LinesStats { kept: 2, merged: 1, deleted: 1, added: 2 });
+    }
+
+    #[test]
+    fn test_merge_lines_options() {
+        // 嵌套的 key 和自定义墓碑
+        let base = r#"{"meta": {"id": "x"}, "v": 1}
+{"meta": {"id": "y"}, "v": 2}
+"#;
+        let overlay = r#"{"meta": {"id": "x"}, "op": "delete"}
+{"meta": {"id": "y"}, "_deleted": true}
+"#;
+        let opts = LinesOptions::new("meta.id").tombstone("op", Value::String("delete".to_string()));
+        let (out, stats) = merge_str(base, overlay, &opts).unwrap();
+        let out: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(out, serde_json::json!({"meta": {"id": "y"}, "v": 2, "_deleted": true}));
+        assert_eq!(stats, LinesStats { kept: 0, merged: 1, deleted: 1, added: 0 });
+    }
+
+    #[test]
+    fn test_merge_lines_errors() {
+        let opts = LinesOptions::new("id").no_tombstone();
+        let r = merge_str("{\"id\": 1}\n{\"name\": 1}\n", "", &opts);
+        assert_eq!(format!("{:#}", r.unwrap_err()), "base line 2: record has no key /id");
+
+        let r = merge_str("", "{\"id\": 1}\n{\"id\": 1}\n", &opts);
+        assert_eq!(r.unwrap_err().to_string(), "duplicate key 1 in overlay line 2");
+
+        let r = merge_str("{\"id\": 1\n", "", &opts);
+        assert!(r.is_err());
+    }
+}
@@ -1,20 +1,796 @@
-use serde_json::Value;
-
+use anyhow::anyhow;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::Hash;
+
+use crate::{json_deep, json_pointer};
+
+#[cfg(feature = "derive")]
+pub use rsutils_derive::Mergeable;
+
+// 与 RFC 7386 JSON Merge Patch 基本一致：b 中的 null 删除对应的 key，数组整体替换
+// 区别是 a 中不是对象的位置直接用 b 替换，新插入的对象中的 null 会被保留，严格的实现见 merge_patch::apply
+// 使用显式的栈代替递归，可以处理嵌套很深的文档
+// 开启 preserve_order 时，已有的 key 位置不变，删除的 key 不影响其他 key 的顺序，新增的 key 按 b 中的顺序追加到最后
 pub fn merge(a: &mut Value, b: Value) {
-    if let Value::Object(a) = a {
-        if let Value::Object(b) = b {
+    let obj_b = match b {
+        Value::Object(obj_b) if a.is_object() => obj_b,
+        b => {
+            json_deep::drop(std::mem::replace(a, b));
+            return;
+        }
+    };
+
+    // 两边都是对象时，把 a 中的子对象取出来合并，完成后再放回父对象的同一个 key
+    let target = std::mem::take(a.as_object_mut().unwrap());
+    let mut stack = vec![(target, obj_b.into_iter(), None)];
+    while let Some((target, patch, _)) = stack.last_mut() {
+        match patch.next() {
+            Some((k, v)) => {
+                if v.is_null() {
+                    if let Some(old) = json_deep::remove_key(target, &k) {
+                        json_deep::drop(old);
+                    }
+                    continue;
+                }
+                match (target.get_mut(&k), v) {
+                    (Some(Value::Object(child)), Value::Object(v)) => {
+                        let child = std::mem::take(child);
+                        stack.push((child, v.into_iter(), Some(k)));
+                    }
+                    (Some(old), v) => json_deep::drop(std::mem::replace(old, v)),
+                    (None, v) => {
+                        target.insert(k, v);
+                    }
+                }
+            }
+            None => {
+                let (target, _, key) = stack.pop().unwrap();
+                match (key, stack.last_mut()) {
+                    (Some(key), Some((parent, _, _))) => parent[&key] = Value::Object(target),
+                    _ => *a = Value::Object(target),
+                }
+            }
+        }
+    }
+}
+
+// 与 merge 相同，但不修改 a、b，返回合并后的新值
+// 多个 overlay 共享同一个大的 base 时可以使用 json_shared::SharedValue 避免整体复制
+pub fn merged(a: &Value, b: &Value) -> Value {
+    let mut v = json_deep::clone(a);
+    merge(&mut v, json_deep::clone(b));
+    v
+}
+
+// 两边都是数组时的合并方式
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrayStrategy {
+    // 用 b 替换 a，与 merge 相同
+    Replace,
+    // b 的元素追加到 a 之后
+    Append,
+    // b 的元素插入到 a 之前
+    Prepend,
+    // 追加 a 中没有的元素，并去除重复
+    Union,
+    // 按下标逐个深度合并，b 更长的部分追加
+    Index,
+    // 按指定字段（如 "id"、"name"）匹配元素后深度合并，匹配不到的追加
+    ByKey(String),
+}
+
+// 自定义合并函数，参数为 a 中原来的值（不存在时为 null）和 b 中的值，返回合并结果
+pub type MergeHook = Box<dyn Fn(&Value, Value) -> Value + Send + Sync>;
+
+pub struct MergeOptions {
+    arrays: Vec<(Vec<String>, ArrayStrategy)>,
+    default_array: ArrayStrategy,
+    delete_marker: Option<Value>,
+    hooks: Vec<(Vec<String>, MergeHook)>,
+    coerce: bool,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions { arrays: vec![], default_array: ArrayStrategy::Replace, delete_marker: None, hooks: vec![], coerce: false }
+    }
+}
+
+impl MergeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // path 为 '/' 或 '.' 分隔的路径模式，"*" 匹配任意一段，"**" 匹配任意多段，如 "/upstreams/*/servers"
+    // 先设置的优先
+    pub fn array(mut self, path: &str, strategy: ArrayStrategy) -> Self {
+        self.arrays.push((json_pointer::parse_pattern(path), strategy));
+        self
+    }
+
+    // 匹配 path 的位置由 hook 决定合并结果，代替默认的合并规则，如 "/limits/*"、"**/tags"
+    // b 中的删除标记（默认为 null）仍然删除对应的 key，不会调用 hook；先设置的优先
+    pub fn hook<F>(mut self, path: &str, hook: F) -> Self
+    where
+        F: Fn(&Value, Value) -> Value + Send + Sync + 'static,
+    {
+        self.hooks.push((json_pointer::parse_pattern(path), Box::new(hook)));
+        self
+    }
+
+    // 没有匹配到 path 的数组使用的合并方式，默认为 Replace
+    pub fn default_array(mut self, strategy: ArrayStrategy) -> Self {
+        self.default_array = strategy;
+        self
+    }
+
+    // 设置删除标记（如 {"$delete": true} 或 "$delete"）后，b 中等于标记的值删除对应的 key
+    // null 不再表示删除，而是作为普通值写入
+    pub fn delete_marker(mut self, marker: Value) -> Self {
+        self.delete_marker = Some(marker);
+        self
+    }
+
+    // b 中的标量按 a 中原值的类型转换，用于来自环境变量或 --set 的字符串值
+    // 如原值为数字时 "8080" 转为 8080，"1.5" 转为 1.5，原值为布尔时 "true" 转为 true，原值为字符串时数字和布尔转为字符串
+    // 无法转换时 merge_with 返回带路径的错误；a 中原值为 null、数组、对象或不存在时不转换
+    pub fn coerce(mut self, coerce: bool) -> Self {
+        self.coerce = coerce;
+        self
+    }
+
+    fn is_delete(&self, v: &Value) -> bool {
+        match &self.delete_marker {
+            Some(marker) => v == marker,
+            None => v.is_null(),
+        }
+    }
+
+    fn find_hook(&self, path: &[String]) -> Option<&MergeHook> {
+        self.hooks.iter().find(|(p, _)| json_pointer::matches(p, path)).map(|(_, h)| h)
+    }
+
+    fn array_strategy(&self, path: &[String]) -> &ArrayStrategy {
+        self.arrays
+            .iter()
+            .find(|(p, _)| json_pointer::matches(p, path))
+            .map(|(_, s)| s)
+            .unwrap_or(&self.default_array)
+    }
+}
+
+// 与 merge 相同，但数组按 opts 中配置的方式合并，key 的顺序规则也与 merge 相同
+// 只有设置了 coerce 才会返回错误，此时先在 a 的副本上合并，出错时 a 不变
+pub fn merge_with(a: &mut Value, b: Value, opts: &MergeOptions) -> anyhow::Result<()> {
+    if !opts.coerce {
+        return merge_path(a, b, opts, &mut vec![]);
+    }
+    let mut v = json_deep::clone(a);
+    merge_path(&mut v, b, opts, &mut vec![])?;
+    json_deep::drop(std::mem::replace(a, v));
+    Ok(())
+}
+
+fn merge_path(a: &mut Value, b: Value, opts: &MergeOptions, path: &mut Vec<String>) -> anyhow::Result<()> {
+    if let Some(hook) = opts.find_hook(path) {
+        *a = hook(a, b);
+        return Ok(());
+    }
+
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
             for (k, v) in b {
+                if opts.is_delete(&v) {
+                    json_deep::remove_key(a, &k);
+                } else {
+                    path.push(k.clone());
+                    merge_path(a.entry(k).or_insert(Value::Null), v, opts, path)?;
+                    path.pop();
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => merge_array(a, b, opts, path)?,
+        // 使用删除标记时，新插入的对象中也不能残留标记
+        (a, Value::Object(b)) if opts.delete_marker.is_some() => {
+            *a = Value::Object(Default::default());
+            merge_path(a, Value::Object(b), opts, path)?;
+        }
+        (a, b) if opts.coerce => *a = coerce(a, b, path)?,
+        (a, b) => *a = b,
+    }
+    Ok(())
+}
+
+// 将 b 转换为 a 的类型，a 或 b 不是标量时原样返回 b
+fn coerce(a: &Value, b: Value, path: &[String]) -> anyhow::Result<Value> {
+    let scalar = |v: &Value| !matches!(v, Value::Null | Value::Array(_) | Value::Object(_));
+    if !scalar(a) || !scalar(&b) || type_name(a) == type_name(&b) {
+        return Ok(b);
+    }
+
+    let converted = match (a, &b) {
+        (Value::Number(n), Value::String(s)) => {
+            let s = s.trim();
+            if n.is_f64() {
+                s.parse::<f64>().ok().and_then(serde_json::Number::from_f64).map(Value::Number)
+            } else if let Ok(v) = s.parse::<i64>() {
+                Some(Value::from(v))
+            } else {
+                s.parse::<u64>().ok().map(Value::from)
+            }
+        }
+        (Value::Bool(_), Value::String(s)) => match s.trim() {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        (Value::String(_), Value::Number(n)) => Some(Value::String(n.to_string())),
+        (Value::String(_), Value::Bool(v)) => Some(Value::String(v.to_string())),
+        _ => None,
+    };
+    converted.ok_or_else(|| anyhow!("can not coerce {} to {} at {}", b, type_name(a), json_pointer::format(path)))
+}
+
+fn merge_array(a: &mut Vec<Value>, b: Vec<Value>, opts: &MergeOptions, path: &mut Vec<String>) -> anyhow::Result<()> {
+    match opts.array_strategy(path) {
+        ArrayStrategy::Replace => *a = b,
+        ArrayStrategy::Append => a.extend(b),
+        ArrayStrategy::Prepend => {
+            let tail = std::mem::replace(a, b);
+            a.extend(tail);
+        }
+        ArrayStrategy::Union => {
+            for v in b {
+                if !a.contains(&v) {
+                    a.push(v);
+                }
+            }
+        }
+        ArrayStrategy::Index => {
+            for (i, v) in b.into_iter().enumerate() {
+                if i < a.len() {
+                    path.push(i.to_string());
+                    merge_path(&mut a[i], v, opts, path)?;
+                    path.pop();
+                } else {
+                    a.push(v);
+                }
+            }
+        }
+        ArrayStrategy::ByKey(key) => {
+            for v in b {
+                let found = match v.get(key) {
+                    Some(id) => a.iter().position(|e| e.get(key) == Some(id)),
+                    None => None,
+                };
+                match found {
+                    Some(i) => {
+                        path.push(i.to_string());
+                        merge_path(&mut a[i], v, opts, path)?;
+                        path.pop();
+                    }
+                    None => a.push(v),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictKind {
+    // 值的类型被改变，如对象被替换为字符串
+    TypeChange { from: &'static str, to: &'static str },
+    // 覆盖或删除了之前某个非 default 层设置的值
+    Overwrite { layer: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub pointer: String,
+    pub kind: ConflictKind,
+}
+
+#[derive(Default)]
+pub struct CheckOptions {
+    fail_on_type_change: bool,
+}
+
+impl CheckOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 出现类型变化时返回错误，且不修改 a
+    pub fn fail_on_type_change(mut self, fail: bool) -> Self {
+        self.fail_on_type_change = fail;
+        self
+    }
+}
+
+// 与 merge 相同，同时返回所有类型发生变化的位置
+pub fn merge_checked(a: &mut Value, b: Value, opts: &CheckOptions) -> anyhow::Result<Vec<Conflict>> {
+    let mut conflicts = vec![];
+    check(Some(a), &b, &mut vec![], &mut None, &mut conflicts);
+    fail_on_type_change(&conflicts, opts)?;
+    merge(a, b);
+    Ok(conflicts)
+}
+
+// 多层依次合并，除类型变化外，还记录每个值由哪一层设置，
+// 后面的层覆盖之前非 default 层设置的值时报告冲突
+pub struct LayeredMerge {
+    value: Value,
+    // 叶子节点（标量、数组、空对象）的路径 -> 设置它的层
+    owners: BTreeMap<Vec<String>, String>,
+    opts: CheckOptions,
+}
+
+impl LayeredMerge {
+    pub fn new(default: Value, opts: CheckOptions) -> Self {
+        LayeredMerge { value: default, owners: BTreeMap::new(), opts }
+    }
+
+    // 合并 default 类的层，不记录来源，只检查类型变化
+    pub fn merge_default(&mut self, b: Value) -> anyhow::Result<Vec<Conflict>> {
+        let conflicts = merge_checked(&mut self.value, b, &self.opts)?;
+        Ok(conflicts)
+    }
+
+    pub fn merge(&mut self, layer: &str, b: Value) -> anyhow::Result<Vec<Conflict>> {
+        let mut conflicts = vec![];
+        let mut owners = Some((self.owners.clone(), layer));
+        check(Some(&self.value), &b, &mut vec![], &mut owners, &mut conflicts);
+        fail_on_type_change(&conflicts, &self.opts)?;
+        self.owners = owners.unwrap().0;
+        merge(&mut self.value, b);
+        Ok(conflicts)
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn into_value(self) -> Value {
+        self.value
+    }
+}
+
+fn fail_on_type_change(conflicts: &[Conflict], opts: &CheckOptions) -> anyhow::Result<()> {
+    if !opts.fail_on_type_change {
+        return Ok(());
+    }
+    let changes: Vec<String> = conflicts
+        .iter()
+        .filter_map(|c| match &c.kind {
+            ConflictKind::TypeChange { from, to } => Some(format!("{}: {} -> {}", c.pointer, from, to)),
+            _ => None,
+        })
+        .collect();
+    if changes.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("merge changes value type at {}", changes.join(", ")))
+    }
+}
+
+fn type_name(v: &Value) -> &'static str {
+    match v {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// 各叶子节点的来源，以及当前合并的层
+type Owners<'a> = Option<(BTreeMap<Vec<String>, String>, &'a str)>;
+
+// 按 merge 的规则遍历 a 和 b，不修改 a
+// owners 不为 None 时检查并更新各叶子节点的来源
+fn check(
+    a: Option<&Value>,
+    b: &Value,
+    path: &mut Vec<String>,
+    owners: &mut Owners,
+    conflicts: &mut Vec<Conflict>,
+) {
+    if let (Some(Value::Object(obj_a)), Value::Object(obj_b)) = (a, b) {
+        for (k, v) in obj_b {
+            path.push(k.clone());
+            if v.is_null() {
+                if obj_a.contains_key(k) {
+                    overwrite(path, None, owners, conflicts);
+                }
+            } else {
+                check(obj_a.get(k), v, path, owners, conflicts);
+            }
+            path.pop();
+        }
+        return;
+    }
+
+    if let Some(a) = a {
+        if !a.is_null() && type_name(a) != type_name(b) {
+            conflicts.push(Conflict {
+                pointer: json_pointer::format(path),
+                kind: ConflictKind::TypeChange { from: type_name(a), to: type_name(b) },
+            });
+        }
+        if a == b {
+            return;
+        }
+    }
+    overwrite(path, Some(b), owners, conflicts);
+}
+
+// path 处的值被替换为 b（None 表示删除），报告被覆盖的来源并记录新的来源
+fn overwrite(
+    path: &mut Vec<String>,
+    b: Option<&Value>,
+    owners: &mut Owners,
+    conflicts: &mut Vec<Conflict>,
+) {
+    let (owners, layer) = match owners {
+        Some((owners, layer)) => (owners, *layer),
+        None => return,
+    };
+
+    let replaced: Vec<Vec<String>> = owners.keys().filter(|p| p.starts_with(path)).cloned().collect();
+    for p in replaced {
+        let owner = owners.remove(&p).unwrap();
+        if owner != layer {
+            conflicts.push(Conflict { pointer: json_pointer::format(&p), kind: ConflictKind::Overwrite { layer: owner } });
+        }
+    }
+
+    if let Some(b) = b {
+        record(path, b, owners, layer);
+    }
+}
+
+fn record(path: &mut Vec<String>, b: &Value, owners: &mut BTreeMap<Vec<String>, String>, layer: &str) {
+    match b {
+        Value::Object(obj) if !obj.is_empty() => {
+            for (k, v) in obj {
+                path.push(k.clone());
+                record(path, v, owners, layer);
+                path.pop();
+            }
+        }
+        _ => {
+            owners.insert(path.clone(), layer.to_string());
+        }
+    }
+}
+
+
+// 三方合并时两边对同一位置做了不同修改的处理方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolution {
+    Ours,
+    Theirs,
+    // 存在冲突时返回错误
+    Fail,
+}
+
+// 三方合并的冲突，None 表示该位置不存在（未设置或已删除）
+#[derive(Debug, Clone, PartialEq)]
+pub struct Merge3Conflict {
+    pub pointer: String,
+    pub base: Option<Value>,
+    pub ours: Option<Value>,
+    pub theirs: Option<Value>,
+}
+
+#[derive(Debug)]
+pub struct Merge3 {
+    pub value: Value,
+    pub conflicts: Vec<Merge3Conflict>,
+}
+
+// 以 base 为共同祖先，合并 ours 和 theirs 各自的修改，对象逐个 key 递归，其他值整体比较
+// 两边对同一位置做了不同修改时记录冲突，并按 resolution 选择一边
+pub fn merge3(base: &Value, ours: &Value, theirs: &Value, resolution: Resolution) -> anyhow::Result<Merge3> {
+    let mut conflicts = vec![];
+    let value = merge3_path(Some(base), Some(ours), Some(theirs), &mut vec![], resolution, &mut conflicts);
+
+    if resolution == Resolution::Fail && !conflicts.is_empty() {
+        let pointers: Vec<&str> = conflicts.iter().map(|c| c.pointer.as_str()).collect();
+        return Err(anyhow!("merge3 conflicts at {}", pointers.join(", ")));
+    }
+    Ok(Merge3 { value: value.unwrap_or(Value::Null), conflicts })
+}
+
+fn merge3_path(
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    path: &mut Vec<String>,
+    resolution: Resolution,
+    conflicts: &mut Vec<Merge3Conflict>,
+) -> Option<Value> {
+    if ours == theirs || theirs == base {
+        return ours.cloned();
+    }
+    if ours == base {
+        return theirs.cloned();
+    }
+
+    if let (Some(Value::Object(o)), Some(Value::Object(t))) = (ours, theirs) {
+        let empty = Map::new();
+        let b = base.and_then(|b| b.as_object()).unwrap_or(&empty);
+        let mut result = Map::new();
+        for k in o.keys().chain(t.keys().filter(|k| !o.contains_key(*k))) {
+            path.push(k.clone());
+            if let Some(v) = merge3_path(b.get(k), o.get(k), t.get(k), path, resolution, conflicts) {
+                result.insert(k.clone(), v);
+            }
+            path.pop();
+        }
+        return Some(Value::Object(result));
+    }
+
+    conflicts.push(Merge3Conflict {
+        pointer: json_pointer::format(path),
+        base: base.cloned(),
+        ours: ours.cloned(),
+        theirs: theirs.cloned(),
+    });
+    match resolution {
+        Resolution::Theirs => theirs.cloned(),
+        Resolution::Ours | Resolution::Fail => ours.cloned(),
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Replaced,
+    Removed,
+}
+
+// merge 对 a 做的一处修改，Added 时 old 为 None，Removed 时 new 为 None
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub pointer: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+    pub kind: ChangeKind,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |v: &Option<Value>| v.as_ref().map(|v| v.to_string()).unwrap_or_default();
+        match self.kind {
+            ChangeKind::Added => write!(f, "added {} = {}", self.pointer, show(&self.new)),
+            ChangeKind::Replaced => write!(f, "changed {} from {} to {}", self.pointer, show(&self.old), show(&self.new)),
+            ChangeKind::Removed => write!(f, "removed {} (was {})", self.pointer, show(&self.old)),
+        }
+    }
+}
+
+// 与 merge 相同，同时返回实际发生的修改，值没有变化的位置不记录
+pub fn merge_logged(a: &mut Value, b: Value) -> Vec<Change> {
+    let mut changes = vec![];
+    merge_logged_path(a, b, &mut vec![], &mut changes);
+    changes
+}
+
+fn merge_logged_path(a: &mut Value, b: Value, path: &mut Vec<String>, changes: &mut Vec<Change>) {
+    if let Value::Object(obj_a) = a {
+        if let Value::Object(obj_b) = b {
+            for (k, v) in obj_b {
+                path.push(k.clone());
                 if v.is_null() {
-                    a.remove(&k);
+                    if let Some(old) = json_deep::remove_key(obj_a, &k) {
+                        changes.push(Change {
+                            pointer: json_pointer::format(path),
+                            old: Some(old),
+                            new: None,
+                            kind: ChangeKind::Removed,
+                        });
+                    }
+                } else if let Some(old) = obj_a.get_mut(&k) {
+                    merge_logged_path(old, v, path, changes);
                 } else {
-                    merge(a.entry(k).or_insert(Value::Null), v);
+                    changes.push(Change {
+                        pointer: json_pointer::format(path),
+                        old: None,
+                        new: Some(v.clone()),
+                        kind: ChangeKind::Added,
+                    });
+                    obj_a.insert(k, v);
                 }
+                path.pop();
             }
             return;
         }
     }
 
-    *a = b;
+    if *a != b {
+        changes.push(Change {
+            pointer: json_pointer::format(path),
+            old: Some(a.take()),
+            new: Some(b.clone()),
+            kind: ChangeKind::Replaced,
+        });
+        *a = b;
+    }
+}
+
+
+// 将 overlay 合并到 path 指向的子树，path 可以是 JSON Pointer 或 "server.tls" 形式
+// 效果与手动构造 {"server": {"tls": overlay}} 再 merge 相同：缺少的中间对象会被创建，
+// 中间不是对象的值被替换为对象，overlay 为 null 时删除 path 处的值
+// 路径经过已有的数组时按下标进入对应元素，"-" 表示追加到最后，下标无效时返回错误且 target 不变
+pub fn merge_at(target: &mut Value, path: &str, overlay: Value) -> anyhow::Result<()> {
+    let segments = json_pointer::parse_path(path)?;
+    if overlay.is_null() && !segments.is_empty() {
+        remove_at(target, path)?;
+        return Ok(());
+    }
+
+    let mut node = target;
+    for (n, seg) in segments.iter().enumerate() {
+        let index = match node {
+            Value::Array(arr) if seg == "-" => Some(arr.len()),
+            Value::Array(arr) => match seg.parse::<usize>().ok().filter(|i| *i < arr.len()) {
+                Some(i) => Some(i),
+                None => {
+                    let at = json_pointer::format(&segments[..n]);
+                    return Err(anyhow!("invalid array index {} at {}, length {}", seg, at, arr.len()));
+                }
+            },
+            _ => None,
+        };
+        node = match index {
+            Some(i) => {
+                let arr = node.as_array_mut().unwrap();
+                if i == arr.len() {
+                    arr.push(Value::Null);
+                }
+                &mut arr[i]
+            }
+            None => {
+                if !node.is_object() {
+                    *node = Value::Object(Map::new());
+                }
+                node.as_object_mut().unwrap().entry(seg.clone()).or_insert(Value::Null)
+            }
+        };
+    }
+    merge(node, overlay);
+    Ok(())
+}
+
+// 删除 path 处的值并返回，不存在时返回 None
+pub fn remove_at(target: &mut Value, path: &str) -> anyhow::Result<Option<Value>> {
+    let mut segments = json_pointer::parse_path(path)?;
+    let last = match segments.pop() {
+        Some(last) => last,
+        None => return Ok(Some(target.take())),
+    };
+
+    let mut node = target;
+    for seg in &segments {
+        node = match child_mut(node, seg) {
+            Some(child) => child,
+            None => return Ok(None),
+        };
+    }
+    Ok(match node {
+        Value::Object(obj) => json_deep::remove_key(obj, &last),
+        Value::Array(arr) => match last.parse::<usize>() {
+            Ok(i) if i < arr.len() => Some(arr.remove(i)),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+fn child_mut<'a>(node: &'a mut Value, seg: &str) -> Option<&'a mut Value> {
+    match node {
+        Value::Object(obj) => obj.get_mut(seg),
+        Value::Array(arr) => seg.parse::<usize>().ok().and_then(move |i| arr.get_mut(i)),
+        _ => None,
+    }
+}
+
+
+// 将 base 和 overlay 序列化后按 merge 的规则合并，再反序列化为 T
+// overlay 可以是只包含部分字段的结构（字段为 None 时需配合 skip_serializing_if 才不会删除 base 中的值）
+pub fn merge_typed<T, U>(base: &T, overlay: &U) -> anyhow::Result<T>
+where
+    T: Serialize + DeserializeOwned,
+    U: Serialize,
+{
+    let mut a = serde_json::to_value(base).map_err(|e| anyhow!("encode base FAILED! {}", e))?;
+    let b = serde_json::to_value(overlay).map_err(|e| anyhow!("encode overlay FAILED! {}", e))?;
+    merge(&mut a, b);
+    serde_json::from_value(a).map_err(|e| anyhow!("decode merged FAILED! {}", e))
+}
+
+// 不经过 serde_json::Value，直接按字段合并，可通过 #[derive(Mergeable)] 为结构体生成实现
+// Option 为 None 时不修改，相当于 merge 中 overlay 缺少这个 key；
+// map 按 key 合并；其他类型（标量、字符串、Vec 等）直接替换，与 merge 中数组整体替换一致
+pub trait Mergeable {
+    fn merge_from(&mut self, overlay: Self);
+}
+
+macro_rules! impl_mergeable_replace {
+    ($($t:ty),*) => {
+        $(
+            impl Mergeable for $t {
+                fn merge_from(&mut self, overlay: Self) {
+                    *self = overlay;
+                }
+            }
+        )*
+    };
+}
+
+impl_mergeable_replace!(bool, char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64, String);
+
+impl<T> Mergeable for Vec<T> {
+    fn merge_from(&mut self, overlay: Self) {
+        *self = overlay;
+    }
+}
+
+impl<T: Mergeable> Mergeable for Option<T> {
+    fn merge_from(&mut self, overlay: Self) {
+        match (self, overlay) {
+            (_, None) => {}
+            (Some(a), Some(b)) => a.merge_from(b),
+            (a, b) => *a = b,
+        }
+    }
+}
+
+impl<T: Mergeable> Mergeable for Box<T> {
+    fn merge_from(&mut self, overlay: Self) {
+        (**self).merge_from(*overlay);
+    }
+}
+
+impl<K: Eq + Hash, V: Mergeable> Mergeable for HashMap<K, V> {
+    fn merge_from(&mut self, overlay: Self) {
+        for (k, v) in overlay {
+            match self.get_mut(&k) {
+                Some(a) => a.merge_from(v),
+                None => {
+                    self.insert(k, v);
+                }
+            }
+        }
+    }
+}
+
+impl<K: Ord, V: Mergeable> Mergeable for BTreeMap<K, V> {
+    fn merge_from(&mut self, overlay: Self) {
+        for (k, v) in overlay {
+            match self.get_mut(&k) {
+                Some(a) => a.merge_from(v),
+                None => {
+                    self.insert(k, v);
+                }
+            }
+        }
+    }
+}
+
+impl Mergeable for Value {
+    fn merge_from(&mut self, overlay: Self) {
+        merge(self, overlay);
+    }
 }
 
 
@@ -375,4 +1151,663 @@ mod test {
 
         // 注意：数组中的 null 不会导致元素被删除，而是替换为 null
     }
+
+    #[test]
+    fn test_merge_with_array_strategies() {
+        // 测试各种数组合并方式
+        let a = json!({"list": [1, 2, 3]});
+        let b = json!({"list": [3, 4, 4]});
+
+        let cases = vec![
+            (ArrayStrategy::Replace, json!([3, 4, 4])),
+            (ArrayStrategy::Append, json!([1, 2, 3, 3, 4, 4])),
+            (ArrayStrategy::Prepend, json!([3, 4, 4, 1, 2, 3])),
+            (ArrayStrategy::Union, json!([1, 2, 3, 4])),
+            (ArrayStrategy::Index, json!([3, 4, 4])),
+        ];
+        for (strategy, expected) in cases {
+            let mut a1 = a.clone();
+            let opts = MergeOptions::new().array("/list", strategy.clone());
+            merge_with(&mut a1, b.clone(), &opts).unwrap();
+            assert_eq!(a1["list"], expected, "{:?} 合并结果不正确", strategy);
+        }
+    }
+
+    #[test]
+    fn test_merge_with_index() {
+        // 测试按下标深度合并
+        let mut a = json!([
+            {"host": "a", "port": 80},
+            {"host": "b", "port": 80}
+        ]);
+        let b = json!([
+            {"port": 8080},
+            {"port": null},
+            {"host": "c"}
+        ]);
+
+        let opts = MergeOptions::new().default_array(ArrayStrategy::Index);
+        merge_with(&mut a, b, &opts).unwrap();
+
+        let expected = json!([
+            {"host": "a", "port": 8080},
+            {"host": "b"},
+            {"host": "c"}
+        ]);
+        assert_eq!(a, expected);
+    }
+
+    #[test]
+    fn test_merge_with_by_key() {
+        // 测试按字段匹配元素后合并，通配符匹配任意一段
+        let mut a = json!({
+            "upstreams": {
+                "web": {"servers": [
+                    {"name": "s1", "weight": 1, "tags": ["a"]},
+                    {"name": "s2", "weight": 1}
+                ]},
+                "api": {"servers": [
+                    {"name": "s3", "weight": 1}
+                ]}
+            },
+            "plugins": ["auth"]
+        });
+        let b = json!({
+            "upstreams": {
+                "web": {"servers": [
+                    {"name": "s2", "weight": 5},
+                    {"name": "s4", "weight": 1},
+                    {"weight": 2}
+                ]},
+                "api": {"servers": [
+                    {"name": "s3", "tags": ["b"]}
+                ]}
+            },
+            "plugins": ["log"]
+        });
+
+        let opts = MergeOptions::new()
+            .array("/upstreams/*/servers", ArrayStrategy::ByKey("name".to_string()))
+            .array("plugins", ArrayStrategy::Append);
+        merge_with(&mut a, b, &opts).unwrap();
+
+        let expected = json!({
+            "upstreams": {
+                "web": {"servers": [
+                    {"name": "s1", "weight": 1, "tags": ["a"]},
+                    {"name": "s2", "weight": 5},
+                    {"name": "s4", "weight": 1},
+                    {"weight": 2}
+                ]},
+                "api": {"servers": [
+                    {"name": "s3", "weight": 1, "tags": ["b"]}
+                ]}
+            },
+            "plugins": ["auth", "log"]
+        });
+        assert_eq!(a, expected);
+    }
+
+    #[test]
+    fn test_merge_with_default_options() {
+        // 默认选项与 merge 结果相同
+        let a = json!({"a": [1, 2], "b": {"c": 1, "d": 2}});
+        let b = json!({"a": [3], "b": {"c": null, "e": [4]}});
+
+        let mut a1 = a.clone();
+        let mut a2 = a.clone();
+        merge(&mut a1, b.clone());
+        merge_with(&mut a2, b, &MergeOptions::new()).unwrap();
+        assert_eq!(a1, a2);
+    }
+
+    #[test]
+    fn test_merge_with_delete_marker() {
+        // 测试删除标记，null 作为普通值保留
+        let mut a = json!({
+            "keep": "value",
+            "delete": "to be deleted",
+            "set_null": "will be null",
+            "nested": {"delete": 1, "keep": 2}
+        });
+        let b = json!({
+            "delete": {"$delete": true},
+            "set_null": null,
+            "nested": {"delete": {"$delete": true}},
+            "new": {"a": 1, "b": {"$delete": true}, "c": null}
+        });
+
+        let opts = MergeOptions::new().delete_marker(json!({"$delete": true}));
+        merge_with(&mut a, b, &opts).unwrap();
+
+        let expected = json!({
+            "keep": "value",
+            "set_null": null,
+            "nested": {"keep": 2},
+            "new": {"a": 1, "c": null}
+        });
+        assert_eq!(a, expected);
+
+        // 标记也可以是字符串
+        let mut a = json!({"a": 1, "b": 2});
+        let opts = MergeOptions::new().delete_marker(json!("$delete"));
+        merge_with(&mut a, json!({"a": "$delete", "b": null}), &opts).unwrap();
+        assert_eq!(a, json!({"b": null}));
+    }
+
+    #[test]
+    fn test_merge_checked_type_changes() {
+        // 测试类型变化的检测
+        let mut a = json!({
+            "server": {"tls": {"cert": "a.pem"}, "port": 80},
+            "list": [1, 2],
+            "name": "demo",
+            "gone": {"a": 1}
+        });
+        let b = json!({
+            "server": {"tls": "off", "port": 8080},
+            "list": {"0": 1},
+            "name": "demo2",
+            "gone": null,
+            "new": 1
+        });
+
+        let mut conflicts = merge_checked(&mut a, b, &CheckOptions::new()).unwrap();
+        conflicts.sort_by(|a, b| a.pointer.cmp(&b.pointer));
+        assert_eq!(
+            conflicts,
+            vec![
+                Conflict { pointer: "/list".to_string(), kind: ConflictKind::TypeChange { from: "array", to: "object" } },
+                Conflict {
+                    pointer: "/server/tls".to_string(),
+                    kind: ConflictKind::TypeChange { from: "object", to: "string" }
+                },
+            ]
+        );
+        assert_eq!(a["server"]["tls"], json!("off"));
+
+        // 出现类型变化时返回错误且不修改 a
+        let mut a = json!({"server": {"tls": {"cert": "a.pem"}}});
+        let opts = CheckOptions::new().fail_on_type_change(true);
+        let r = merge_checked(&mut a, json!({"server": {"tls": "off"}}), &opts);
+        assert_eq!(r.unwrap_err().to_string(), "merge changes value type at /server/tls: object -> string");
+        assert_eq!(a, json!({"server": {"tls": {"cert": "a.pem"}}}));
+    }
+
+    #[test]
+    fn test_layered_merge_overwrite() {
+        // 测试覆盖之前非 default 层设置的值
+        let mut m = LayeredMerge::new(json!({"port": 80, "log": {"level": "info"}}), CheckOptions::new());
+
+        // default 层设置的值被覆盖不算冲突
+        let c = m.merge("user", json!({"port": 8080, "log": {"file": "a.log"}, "extra": {"a": 1}})).unwrap();
+        assert_eq!(c, vec![]);
+
+        // 相同的值不算冲突
+        let c = m.merge("env", json!({"port": 8080, "extra": {"b": 2}})).unwrap();
+        assert_eq!(c, vec![]);
+
+        let mut c = m.merge("cmdline", json!({"port": 9090, "log": null, "extra": "x"})).unwrap();
+        c.sort_by(|a, b| a.pointer.cmp(&b.pointer));
+        let overwrite = |p: &str, layer: &str| Conflict {
+            pointer: p.to_string(),
+            kind: ConflictKind::Overwrite { layer: layer.to_string() },
+        };
+        assert_eq!(
+            c,
+            vec![
+                Conflict { pointer: "/extra".to_string(), kind: ConflictKind::TypeChange { from: "object", to: "string" } },
+                overwrite("/extra/a", "user"),
+                overwrite("/extra/b", "env"),
+                overwrite("/log/file", "user"),
+                overwrite("/port", "user"),
+            ]
+        );
+        assert_eq!(m.into_value(), json!({"port": 9090, "extra": "x"}));
+    }
+
+    #[test]
+    fn test_merge3_no_conflicts() {
+        // 测试两边不冲突的修改都被应用
+        let base = json!({
+            "server": {"host": "0.0.0.0", "port": 80},
+            "log": {"level": "info"},
+            "removed": 1,
+            "list": [1, 2]
+        });
+        let ours = json!({
+            "server": {"host": "127.0.0.1", "port": 80},
+            "log": {"level": "info"},
+            "list": [1, 2],
+            "local": true
+        });
+        let theirs = json!({
+            "server": {"host": "0.0.0.0", "port": 8080},
+            "log": {"level": "info", "file": "a.log"},
+            "removed": 1,
+            "list": [1, 2, 3]
+        });
+
+        let r = merge3(&base, &ours, &theirs, Resolution::Fail).unwrap();
+        let expected = json!({
+            "server": {"host": "127.0.0.1", "port": 8080},
+            "log": {"level": "info", "file": "a.log"},
+            "list": [1, 2, 3],
+            "local": true
+        });
+        assert_eq!(r.value, expected);
+        assert!(r.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_merge3_conflicts() {
+        // 测试两边对同一位置做了不同修改
+        let base = json!({"port": 80, "tls": {"cert": "a.pem"}, "name": "demo"});
+        let ours = json!({"port": 8080, "name": "demo"});
+        let theirs = json!({"port": 9090, "tls": {"cert": "b.pem"}, "name": "demo", "new": {"a": 1}});
+
+        let mut r = merge3(&base, &ours, &theirs, Resolution::Ours).unwrap();
+        r.conflicts.sort_by(|a, b| a.pointer.cmp(&b.pointer));
+        assert_eq!(r.value, json!({"port": 8080, "name": "demo", "new": {"a": 1}}));
+        assert_eq!(
+            r.conflicts,
+            vec![
+                Merge3Conflict {
+                    pointer: "/port".to_string(),
+                    base: Some(json!(80)),
+                    ours: Some(json!(8080)),
+                    theirs: Some(json!(9090)),
+                },
+                Merge3Conflict {
+                    pointer: "/tls".to_string(),
+                    base: Some(json!({"cert": "a.pem"})),
+                    ours: None,
+                    theirs: Some(json!({"cert": "b.pem"})),
+                },
+            ]
+        );
+
+        let r = merge3(&base, &ours, &theirs, Resolution::Theirs).unwrap();
+        assert_eq!(r.value, json!({"port": 9090, "tls": {"cert": "b.pem"}, "name": "demo", "new": {"a": 1}}));
+        assert_eq!(r.conflicts.len(), 2);
+
+        let r = merge3(&base, &ours, &theirs, Resolution::Fail);
+        assert!(r.unwrap_err().to_string().starts_with("merge3 conflicts at /"));
+    }
+
+    #[test]
+    fn test_merge3_both_added() {
+        // 两边新增了同一个对象，按 key 递归合并
+        let base = json!({});
+        let ours = json!({"db": {"host": "a", "pool": 10}});
+        let theirs = json!({"db": {"host": "a", "timeout": 5}});
+
+        let r = merge3(&base, &ours, &theirs, Resolution::Fail).unwrap();
+        assert_eq!(r.value, json!({"db": {"host": "a", "pool": 10, "timeout": 5}}));
+    }
+
+    #[test]
+    fn test_merge_logged() {
+        // 测试返回的修改记录
+        let a = json!({
+            "server": {"host": "0.0.0.0", "port": 80},
+            "log": {"level": "info", "file": "a.log"},
+            "list": [1, 2]
+        });
+        let b = json!({
+            "server": {"host": "0.0.0.0", "port": 8080},
+            "log": {"file": null, "missing": null},
+            "list": [1, 2],
+            "new": {"a": 1}
+        });
+
+        let mut a1 = a.clone();
+        let mut changes = merge_logged(&mut a1, b.clone());
+        changes.sort_by(|a, b| a.pointer.cmp(&b.pointer));
+
+        let messages: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "removed /log/file (was \"a.log\")",
+                "added /new = {\"a\":1}",
+                "changed /server/port from 80 to 8080",
+            ]
+        );
+        assert_eq!(changes[2].kind, ChangeKind::Replaced);
+        assert_eq!(changes[2].old, Some(json!(80)));
+
+        // 结果与 merge 相同
+        let mut a2 = a.clone();
+        merge(&mut a2, b);
+        assert_eq!(a1, a2);
+    }
+
+    #[test]
+    fn test_merge_logged_type_change() {
+        // 类型变化记录为整体替换
+        let mut a = json!({"tls": {"cert": "a.pem"}});
+        let changes = merge_logged(&mut a, json!({"tls": "off"}));
+        assert_eq!(
+            changes,
+            vec![Change {
+                pointer: "/tls".to_string(),
+                old: Some(json!({"cert": "a.pem"})),
+                new: Some(json!("off")),
+                kind: ChangeKind::Replaced,
+            }]
+        );
+
+        let mut a = json!({"a": 1});
+        assert!(merge_logged(&mut a, json!({"a": 1})).is_empty());
+    }
+
+    #[test]
+    fn test_merge_deeply_nested() {
+        // 测试嵌套 10 万层的文档不会栈溢出
+        use crate::json_deep::{self, nested};
+
+        let mut a = nested(100_000, json!({"keep": 1, "change": 1}));
+        let b = nested(100_000, json!({"change": 2, "add": 3}));
+        merge(&mut a, b);
+
+        let expected = nested(100_000, json!({"keep": 1, "change": 2, "add": 3}));
+        assert!(json_deep::eq(&a, &expected), "深层合并结果不正确");
+        json_deep::drop(expected);
+
+        // 深层子树被替换为标量
+        merge(&mut a, json!({"a": 1}));
+        assert_eq!(a, json!({"a": 1}));
+
+        // 深层子树替换标量
+        let b = nested(100_000, json!(1));
+        let expected = json_deep::clone(&b);
+        merge(&mut a, b);
+        assert!(json_deep::eq(&a, &expected), "深层子树替换结果不正确");
+        json_deep::drop(expected);
+        json_deep::drop(a);
+    }
+
+    #[test]
+    fn test_merge_at() {
+        // 测试合并到指定路径
+        let mut a = json!({
+            "server": {"port": 80, "tls": {"cert": "a.pem", "key": "a.key"}},
+            "name": "demo"
+        });
+
+        merge_at(&mut a, "/server/tls", json!({"cert": "b.pem", "key": null})).unwrap();
+        merge_at(&mut a, "server.port", json!(8080)).unwrap();
+        // 自动创建中间对象
+        merge_at(&mut a, "log.file", json!({"path": "a.log"})).unwrap();
+        // 中间不是对象时替换为对象
+        merge_at(&mut a, "/name/first", json!("x")).unwrap();
+
+        let expected = json!({
+            "server": {"port": 8080, "tls": {"cert": "b.pem"}},
+            "log": {"file": {"path": "a.log"}},
+            "name": {"first": "x"}
+        });
+        assert_eq!(a, expected);
+
+        // 与手动构造 overlay 的结果相同
+        let mut a1 = json!({"a": {"b": 1}});
+        let mut a2 = a1.clone();
+        merge_at(&mut a1, "/a/c/d", json!({"e": 1})).unwrap();
+        merge(&mut a2, json!({"a": {"c": {"d": {"e": 1}}}}));
+        assert_eq!(a1, a2);
+
+        // 空路径表示根
+        let mut a = json!({"a": 1});
+        merge_at(&mut a, "", json!({"b": 2})).unwrap();
+        assert_eq!(a, json!({"a": 1, "b": 2}));
+    }
+
+    #[test]
+    fn test_merge_at_array_and_null() {
+        // 路径经过已有数组时按下标进入元素
+        let mut a = json!({"servers": [{"host": "a"}, {"host": "b"}]});
+        merge_at(&mut a, "/servers/1", json!({"port": 80})).unwrap();
+        assert_eq!(a, json!({"servers": [{"host": "a"}, {"host": "b", "port": 80}]}));
+
+        // overlay 为 null 时删除
+        merge_at(&mut a, "/servers/0/host", Value::Null).unwrap();
+        merge_at(&mut a, "/missing/x", Value::Null).unwrap();
+        assert_eq!(a, json!({"servers": [{}, {"host": "b", "port": 80}]}));
+
+        // "-" 追加元素，下标越界或不是数字时返回错误，不修改数组
+        merge_at(&mut a, "/servers/-", json!({"host": "c"})).unwrap();
+        assert_eq!(a["servers"][2], json!({"host": "c"}));
+        let r = merge_at(&mut a, "/servers/5", json!(1));
+        assert_eq!(r.unwrap_err().to_string(), "invalid array index 5 at /servers, length 3");
+        assert!(merge_at(&mut a, "servers.x.port", json!(1)).is_err());
+        assert_eq!(a["servers"].as_array().unwrap().len(), 3, "数组应不变");
+    }
+
+    #[test]
+    fn test_remove_at() {
+        let mut a = json!({"server": {"tls": {"cert": "a.pem"}, "port": 80}, "list": [1, 2, 3]});
+
+        assert_eq!(remove_at(&mut a, "/server/tls").unwrap(), Some(json!({"cert": "a.pem"})));
+        assert_eq!(remove_at(&mut a, "list.1").unwrap(), Some(json!(2)));
+        assert_eq!(remove_at(&mut a, "/server/missing/x").unwrap(), None);
+        assert_eq!(remove_at(&mut a, "/list/9").unwrap(), None);
+        assert_eq!(a, json!({"server": {"port": 80}, "list": [1, 3]}));
+
+        assert_eq!(remove_at(&mut a, "").unwrap(), Some(json!({"server": {"port": 80}, "list": [1, 3]})));
+        assert_eq!(a, Value::Null);
+    }
+
+    #[test]
+    fn test_merge_typed() {
+        // 测试结构体经过 Value 合并
+        use serde::Deserialize;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Server {
+            host: String,
+            port: u16,
+            tags: Vec<String>,
+        }
+
+        #[derive(Serialize)]
+        struct Overlay {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            host: Option<String>,
+            port: u16,
+        }
+
+        let base = Server { host: "0.0.0.0".to_string(), port: 80, tags: vec!["a".to_string()] };
+        let merged = merge_typed(&base, &Overlay { host: None, port: 8080 }).unwrap();
+        assert_eq!(merged, Server { host: "0.0.0.0".to_string(), port: 8080, tags: vec!["a".to_string()] });
+
+        // 结果无法反序列化时返回错误
+        let r = merge_typed(&base, &json!({"port": "x"}));
+        assert!(r.unwrap_err().to_string().starts_with("decode merged FAILED!"));
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive_mergeable() {
+        // 测试按字段合并，结果应与经过 Value 合并相同
+        use serde::Deserialize;
+
+        #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Mergeable)]
+        struct Limits {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            rate: Option<u32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            burst: Option<u32>,
+        }
+
+        #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Mergeable)]
+        struct Request {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            timeout: Option<u64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            limits: Option<Limits>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            tags: Option<Vec<String>>,
+            headers: BTreeMap<String, String>,
+        }
+
+        #[derive(Debug, PartialEq, Mergeable)]
+        struct Pair(Option<u8>, String);
+
+        #[derive(Debug, PartialEq, Mergeable)]
+        struct Wrapper<T, U: Clone> {
+            inner: T,
+            extra: Option<U>,
+        }
+
+        let base = Request {
+            timeout: Some(30),
+            limits: Some(Limits { rate: Some(100), burst: Some(10) }),
+            tags: Some(vec!["a".to_string()]),
+            headers: vec![("a".to_string(), "1".to_string())].into_iter().collect(),
+        };
+        let overlay = Request {
+            timeout: None,
+            limits: Some(Limits { rate: Some(50), burst: None }),
+            tags: Some(vec!["b".to_string()]),
+            headers: vec![("b".to_string(), "2".to_string())].into_iter().collect(),
+        };
+
+        let expected = merge_typed(&base, &overlay).unwrap();
+        let mut merged = base.clone();
+        merged.merge_from(overlay);
+        assert_eq!(merged, expected);
+        assert_eq!(merged.limits, Some(Limits { rate: Some(50), burst: Some(10) }));
+        assert_eq!(merged.headers.len(), 2);
+
+        let mut p = Pair(Some(1), "a".to_string());
+        p.merge_from(Pair(None, "b".to_string()));
+        assert_eq!(p, Pair(Some(1), "b".to_string()));
+
+        // 泛型字段
+        let mut w = Wrapper { inner: Limits { rate: Some(1), burst: Some(2) }, extra: Some(1u8) };
+        w.merge_from(Wrapper { inner: Limits { rate: None, burst: Some(3) }, extra: None });
+        assert_eq!(w, Wrapper { inner: Limits { rate: Some(1), burst: Some(3) }, extra: Some(1) });
+    }
+
+    #[test]
+    fn test_merge_with_hooks() {
+        // 测试自定义合并函数：限流取更严格的值，标签取并集，描述拼接
+        let mut a = json!({
+            "limits": {"rate": 100, "burst": 10},
+            "api": {"tags": ["a", "b"], "desc": "base"},
+            "tags": ["x"]
+        });
+        let b = json!({
+            "limits": {"rate": 50, "burst": 20, "conn": 5},
+            "api": {"tags": ["b", "c"], "desc": "overlay"},
+            "tags": ["y"]
+        });
+
+        let opts = MergeOptions::new()
+            .hook("/limits/*", |a, b| match (a.as_u64(), b.as_u64()) {
+                (Some(x), Some(y)) => json!(x.min(y)),
+                _ => b,
+            })
+            .hook("**/tags", |a, b| {
+                let mut tags = a.as_array().cloned().unwrap_or_default();
+                for t in b.as_array().cloned().unwrap_or_default() {
+                    if !tags.contains(&t) {
+                        tags.push(t);
+                    }
+                }
+                Value::Array(tags)
+            })
+            .hook("api.desc", |a, b| json!(format!("{} {}", a.as_str().unwrap_or(""), b.as_str().unwrap_or(""))))
+            // 先设置的优先，不会调用
+            .hook("/api/desc", |_, b| b);
+        merge_with(&mut a, b, &opts).unwrap();
+
+        let expected = json!({
+            "limits": {"rate": 50, "burst": 10, "conn": 5},
+            "api": {"tags": ["a", "b", "c"], "desc": "base overlay"},
+            "tags": ["x", "y"]
+        });
+        assert_eq!(a, expected);
+
+        // null 仍然删除，不调用 hook
+        merge_with(&mut a, json!({"limits": {"rate": null}}), &opts).unwrap();
+        assert_eq!(a["limits"], json!({"burst": 10, "conn": 5}));
+    }
+
+    #[test]
+    fn test_merged() {
+        let a = json!({"a": 1, "b": {"c": 2, "d": 3}});
+        let b = json!({"b": {"c": null, "e": 4}, "f": [1]});
+        let v = merged(&a, &b);
+        assert_eq!(v, json!({"a": 1, "b": {"d": 3, "e": 4}, "f": [1]}));
+        // 输入保持不变
+        assert_eq!(a, json!({"a": 1, "b": {"c": 2, "d": 3}}));
+        assert_eq!(b, json!({"b": {"c": null, "e": 4}, "f": [1]}));
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[test]
+    fn test_merge_preserve_order() {
+        let mut a: Value = serde_json::from_str(r#"{"z": 1, "m": {"y": 1, "b": 2, "x": 3}, "a": 1}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"new2": 1, "m": {"b": null, "c": 1, "y": 2}, "z": 2, "new1": 2}"#).unwrap();
+
+        let mut c = a.clone();
+        merge_with(&mut c, b.clone(), &MergeOptions::new()).unwrap();
+        merge(&mut a, b);
+        let expected = r#"{"z":2,"m":{"y":2,"x":3,"c":1},"a":1,"new2":1,"new1":2}"#;
+        assert_eq!(a.to_string(), expected);
+        assert_eq!(c.to_string(), expected);
+
+        let mut a: Value = serde_json::from_str(r#"{"z": 1, "a": {"y": 1}}"#).unwrap();
+        merge_at(&mut a, "/a/b", json!(1)).unwrap();
+        remove_at(&mut a, "/z").unwrap();
+        assert_eq!(a.to_string(), r#"{"a":{"y":1,"b":1}}"#);
+    }
+
+    #[test]
+    fn test_merge_with_coerce() {
+        let mut a = json!({
+            "port": 80,
+            "ratio": 0.5,
+            "debug": false,
+            "name": "web",
+            "servers": [{"weight": 1}],
+            "tags": ["a"]
+        });
+        let b = json!({
+            "port": " 8080",
+            "ratio": "1.5",
+            "debug": "true",
+            "name": 1,
+            "servers": [{"weight": "3"}],
+            "tags": "b",
+            "new": "1"
+        });
+        let opts = MergeOptions::new().coerce(true).default_array(ArrayStrategy::Index);
+        merge_with(&mut a, b, &opts).unwrap();
+        let expected = json!({
+            "port": 8080,
+            "ratio": 1.5,
+            "debug": true,
+            "name": "1",
+            "servers": [{"weight": 3}],
+            "tags": "b",
+            "new": "1"
+        });
+        assert_eq!(a, expected);
+
+        // 无法转换时返回带路径的错误，a 不变
+        let r = merge_with(&mut a, json!({"port": "9090", "servers": [{"weight": "x"}]}), &opts);
+        assert_eq!(r.unwrap_err().to_string(), r#"can not coerce "x" to number at /servers/0/weight"#);
+        assert_eq!(a, expected, "出错时 a 应不变");
+        let r = merge_with(&mut a, json!({"port": "1.5"}), &opts);
+        assert_eq!(r.unwrap_err().to_string(), r#"can not coerce "1.5" to number at /port"#);
+        let r = merge_with(&mut a, json!({"debug": 1}), &opts);
+        assert_eq!(r.unwrap_err().to_string(), "can not coerce 1 to boolean at /debug");
+
+        // 未设置 coerce 时直接替换
+        merge_with(&mut a, json!({"port": "8080"}), &MergeOptions::new()).unwrap();
+        assert_eq!(a["port"], json!("8080"));
+    }
 }
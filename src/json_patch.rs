@@ -0,0 +1,397 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{json_deep, json_pointer};
+
+// RFC 6902 JSON Patch 的一个操作，path 和 from 为 JSON Pointer
+// 序列化为 {"op": "add", "path": "/a", "value": 1} 的形式
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+impl PatchOp {
+    pub fn path(&self) -> &str {
+        match self {
+            PatchOp::Add { path, .. }
+            | PatchOp::Remove { path }
+            | PatchOp::Replace { path, .. }
+            | PatchOp::Move { path, .. }
+            | PatchOp::Copy { path, .. }
+            | PatchOp::Test { path, .. } => path,
+        }
+    }
+
+    // 在 path 和 from 前加上 prefix，用于把子文档的 patch 用到上层文档
+    pub fn with_prefix(self, prefix: &str) -> PatchOp {
+        let p = |path: String| format!("{}{}", prefix, path);
+        match self {
+            PatchOp::Add { path, value } => PatchOp::Add { path: p(path), value },
+            PatchOp::Remove { path } => PatchOp::Remove { path: p(path) },
+            PatchOp::Replace { path, value } => PatchOp::Replace { path: p(path), value },
+            PatchOp::Move { from, path } => PatchOp::Move { from: p(from), path: p(path) },
+            PatchOp::Copy { from, path } => PatchOp::Copy { from: p(from), path: p(path) },
+            PatchOp::Test { path, value } => PatchOp::Test { path: p(path), value },
+        }
+    }
+}
+
+// 按顺序应用 RFC 6902 JSON Patch，任何一个操作（包括 test）失败时撤销已经执行的操作，doc 保持不变
+// 错误信息中包含失败操作的序号
+pub fn apply(doc: &mut Value, ops: &[PatchOp]) -> anyhow::Result<()> {
+    let mut undo = vec![];
+    for (i, op) in ops.iter().enumerate() {
+        if let Err(e) = apply_op(doc, op, &mut undo) {
+            for u in undo.into_iter().rev() {
+                revert(doc, u);
+            }
+            return Err(e.context(format!("patch op {}", i)));
+        }
+    }
+    Ok(())
+}
+
+// 撤销一个修改的方法
+enum Undo {
+    // 删除插入的值
+    Remove(Vec<String>),
+    // 放回被删除的值，对象时 usize 为原来 key 的位置
+    Insert(Vec<String>, usize, Value),
+    // 恢复被替换的值
+    Replace(Vec<String>, Value),
+}
+
+fn apply_op(doc: &mut Value, op: &PatchOp, undo: &mut Vec<Undo>) -> anyhow::Result<()> {
+    match op {
+        PatchOp::Add { path, value } => {
+            undo.push(put(doc, &json_pointer::parse(path)?, value.clone(), false)?);
+        }
+        PatchOp::Remove { path } => {
+            let path = json_pointer::parse(path)?;
+            let (v, index) = take(doc, &path)?;
+            undo.push(Undo::Insert(path, index, v));
+        }
+        PatchOp::Replace { path, value } => {
+            undo.push(put(doc, &json_pointer::parse(path)?, value.clone(), true)?);
+        }
+        PatchOp::Move { from, path } => {
+            if from == path {
+                // 位置不变，但 from 仍然必须存在
+                if doc.pointer(from).is_none() {
+                    return Err(anyhow!("path {} not found", from));
+                }
+                return Ok(());
+            }
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(anyhow!("can not move {} into its own child {}", from, path));
+            }
+            let from = json_pointer::parse(from)?;
+            let (v, index) = take(doc, &from)?;
+            undo.push(Undo::Insert(from, index, json_deep::clone(&v)));
+            undo.push(put(doc, &json_pointer::parse(path)?, v, false)?);
+        }
+        PatchOp::Copy { from, path } => {
+            let v = match doc.pointer(from) {
+                Some(v) => json_deep::clone(v),
+                None => return Err(anyhow!("path {} not found", from)),
+            };
+            undo.push(put(doc, &json_pointer::parse(path)?, v, false)?);
+        }
+        PatchOp::Test { path, value } => match doc.pointer(path) {
+            // 数字按数值比较
+            Some(v) if json_deep::eq_numeric(v, value) => {}
+            Some(v) => return Err(anyhow!("test {} FAILED! expected {}, found {}", path, value, v)),
+            None => return Err(anyhow!("test {} FAILED! path not found", path)),
+        },
+    }
+    Ok(())
+}
+
+// 数组下标不能有前导 0，也不能为负数
+fn array_index(token: &str, pointer: &str) -> anyhow::Result<usize> {
+    if token.is_empty() || !token.bytes().all(|c| c.is_ascii_digit()) || (token.len() > 1 && token.starts_with('0')) {
+        return Err(anyhow!("invalid array index {:?} at {}", token, pointer));
+    }
+    token.parse().map_err(|_| anyhow!("invalid array index {:?} at {}", token, pointer))
+}
+
+fn container_mut<'v>(doc: &'v mut Value, parent: &[String], pointer: &str) -> anyhow::Result<&'v mut Value> {
+    let mut cur = doc;
+    for k in parent {
+        cur = match cur {
+            Value::Object(obj) => obj.get_mut(k),
+            Value::Array(arr) => {
+                let i = array_index(k, pointer)?;
+                arr.get_mut(i)
+            }
+            _ => None,
+        }
+        .ok_or_else(|| anyhow!("path {} not found", pointer))?;
+    }
+    Ok(cur)
+}
+
+// 写入 path，replace 为 true 时 path 必须已存在，否则按 add 的规则插入（数组中插入而不是覆盖）
+fn put(doc: &mut Value, path: &[String], value: Value, replace: bool) -> anyhow::Result<Undo> {
+    let (last, parent) = match path.split_last() {
+        Some(v) => v,
+        None => return Ok(Undo::Replace(vec![], std::mem::replace(doc, value))),
+    };
+    let pointer = json_pointer::format(path);
+    match container_mut(doc, parent, &pointer)? {
+        Value::Object(obj) => match obj.get_mut(last) {
+            Some(old) => Ok(Undo::Replace(path.to_vec(), std::mem::replace(old, value))),
+            None if replace => Err(anyhow!("path {} not found", pointer)),
+            None => {
+                obj.insert(last.clone(), value);
+                Ok(Undo::Remove(path.to_vec()))
+            }
+        },
+        Value::Array(arr) => {
+            let i = if last == "-" && !replace { arr.len() } else { array_index(last, &pointer)? };
+            if replace {
+                match arr.get_mut(i) {
+                    Some(old) => Ok(Undo::Replace(path.to_vec(), std::mem::replace(old, value))),
+                    None => Err(anyhow!("array index {} out of range at {}", i, pointer)),
+                }
+            } else if i <= arr.len() {
+                arr.insert(i, value);
+                let mut inserted = parent.to_vec();
+                inserted.push(i.to_string());
+                Ok(Undo::Remove(inserted))
+            } else {
+                Err(anyhow!("array index {} out of range at {}", i, pointer))
+            }
+        }
+        _ => Err(anyhow!("path {} not found", pointer)),
+    }
+}
+
+// 删除 path 处的值，返回该值和它在对象或数组中的位置
+fn take(doc: &mut Value, path: &[String]) -> anyhow::Result<(Value, usize)> {
+    let (last, parent) = match path.split_last() {
+        Some(v) => v,
+        None => return Err(anyhow!("can not remove the root")),
+    };
+    let pointer = json_pointer::format(path);
+    match container_mut(doc, parent, &pointer)? {
+        Value::Object(obj) => {
+            let index = obj.keys().position(|k| k == last);
+            match (index, json_deep::remove_key(obj, last)) {
+                (Some(index), Some(v)) => Ok((v, index)),
+                _ => Err(anyhow!("path {} not found", pointer)),
+            }
+        }
+        Value::Array(arr) => {
+            let i = array_index(last, &pointer)?;
+            if i < arr.len() {
+                Ok((arr.remove(i), i))
+            } else {
+                Err(anyhow!("array index {} out of range at {}", i, pointer))
+            }
+        }
+        _ => Err(anyhow!("path {} not found", pointer)),
+    }
+}
+
+// 撤销的路径都是执行时确认存在的，不会失败
+fn revert(doc: &mut Value, undo: Undo) {
+    match undo {
+        Undo::Remove(path) => {
+            take(doc, &path).expect("revert patch");
+        }
+        Undo::Replace(path, v) => {
+            put(doc, &path, v, true).expect("revert patch");
+        }
+        Undo::Insert(path, index, v) => {
+            let (last, parent) = path.split_last().expect("revert patch");
+            match container_mut(doc, parent, "").expect("revert patch") {
+                Value::Object(obj) => json_deep::insert_key_at(obj, index, last.clone(), v),
+                Value::Array(arr) => arr.insert(index, v),
+                _ => unreachable!("revert patch"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_serde() {
+        let doc = json!([
+            {"op": "test", "path": "/a/b/c", "value": "foo"},
+            {"op": "remove", "path": "/a/b/c"},
+            {"op": "add", "path": "/a/b/c", "value": ["foo", "bar"]},
+            {"op": "replace", "path": "/a/b/c", "value": 42},
+            {"op": "move", "from": "/a/b/c", "path": "/a/b/d"},
+            {"op": "copy", "from": "/a/b/d", "path": "/a/b/e"}
+        ]);
+        let ops: Vec<PatchOp> = serde_json::from_value(doc.clone()).unwrap();
+        assert_eq!(ops[1], PatchOp::Remove { path: "/a/b/c".to_string() });
+        assert_eq!(ops[4], PatchOp::Move { from: "/a/b/c".to_string(), path: "/a/b/d".to_string() });
+        assert_eq!(ops[5].path(), "/a/b/e");
+        assert_eq!(serde_json::to_value(&ops).unwrap(), doc);
+
+        // 未知的操作和缺少字段都应报错
+        assert!(serde_json::from_value::<PatchOp>(json!({"op": "merge", "path": "/a"})).is_err());
+        assert!(serde_json::from_value::<PatchOp>(json!({"op": "add", "path": "/a"})).is_err());
+    }
+
+    fn ops(v: Value) -> Vec<PatchOp> {
+        serde_json::from_value(v).unwrap()
+    }
+
+    // RFC 6902 Appendix A，expected 为 None 时应返回错误
+    fn rfc_vectors() -> Vec<(Value, Value, Option<Value>)> {
+        vec![
+            // A.1
+            (json!({"foo": "bar"}), json!([{"op": "add", "path": "/baz", "value": "qux"}]), Some(json!({"baz": "qux", "foo": "bar"}))),
+            // A.2
+            (
+                json!({"foo": ["bar", "baz"]}),
+                json!([{"op": "add", "path": "/foo/1", "value": "qux"}]),
+                Some(json!({"foo": ["bar", "qux", "baz"]})),
+            ),
+            // A.3
+            (json!({"baz": "qux", "foo": "bar"}), json!([{"op": "remove", "path": "/baz"}]), Some(json!({"foo": "bar"}))),
+            // A.4
+            (json!({"foo": ["bar", "qux", "baz"]}), json!([{"op": "remove", "path": "/foo/1"}]), Some(json!({"foo": ["bar", "baz"]}))),
+            // A.5
+            (
+                json!({"baz": "qux", "foo": "bar"}),
+                json!([{"op": "replace", "path": "/baz", "value": "boo"}]),
+                Some(json!({"baz": "boo", "foo": "bar"})),
+            ),
+            // A.6
+            (
+                json!({"foo": {"bar": "baz", "waldo": "fred"}, "qux": {"corge": "grault"}}),
+                json!([{"op": "move", "from": "/foo/waldo", "path": "/qux/thud"}]),
+                Some(json!({"foo": {"bar": "baz"}, "qux": {"corge": "grault", "thud": "fred"}})),
+            ),
+            // A.7
+            (
+                json!({"foo": ["all", "grass", "cows", "eat"]}),
+                json!([{"op": "move", "from": "/foo/1", "path": "/foo/3"}]),
+                Some(json!({"foo": ["all", "cows", "eat", "grass"]})),
+            ),
+            // A.8
+            (
+                json!({"baz": "qux", "foo": ["a", 2, "c"]}),
+                json!([{"op": "test", "path": "/baz", "value": "qux"}, {"op": "test", "path": "/foo/1", "value": 2}]),
+                Some(json!({"baz": "qux", "foo": ["a", 2, "c"]})),
+            ),
+            // A.9
+            (json!({"baz": "qux"}), json!([{"op": "test", "path": "/baz", "value": "bar"}]), None),
+            // A.10
+            (
+                json!({"foo": "bar"}),
+                json!([{"op": "add", "path": "/child", "value": {"grandchild": {}}}]),
+                Some(json!({"foo": "bar", "child": {"grandchild": {}}})),
+            ),
+            // A.11
+            (
+                json!({"foo": "bar"}),
+                json!([{"op": "add", "path": "/baz", "value": "qux", "xyz": 123}]),
+                Some(json!({"foo": "bar", "baz": "qux"})),
+            ),
+            // A.12
+            (json!({"foo": "bar"}), json!([{"op": "add", "path": "/baz/bat", "value": "qux"}]), None),
+            // A.14
+            (
+                json!({"/": 9, "~1": 10}),
+                json!([{"op": "test", "path": "/~01", "value": 10}]),
+                Some(json!({"/": 9, "~1": 10})),
+            ),
+            // A.15
+            (json!({"/": 9, "~1": 10}), json!([{"op": "test", "path": "/~01", "value": "10"}]), None),
+            // A.16
+            (
+                json!({"foo": ["bar"]}),
+                json!([{"op": "add", "path": "/foo/-", "value": ["abc", "def"]}]),
+                Some(json!({"foo": ["bar", ["abc", "def"]]})),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_apply_rfc_vectors() {
+        for (doc, patch, expected) in rfc_vectors() {
+            let mut target = doc.clone();
+            let r = apply(&mut target, &ops(patch.clone()));
+            match expected {
+                Some(expected) => {
+                    assert!(r.is_ok(), "apply({}, {}) FAILED: {:?}", doc, patch, r);
+                    assert_eq!(target, expected, "apply({}, {})", doc, patch);
+                }
+                None => {
+                    assert!(r.is_err(), "apply({}, {}) 应该失败", doc, patch);
+                    assert_eq!(target, doc, "失败后应保持不变");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_rollback() {
+        let doc = json!({"a": {"b": [1, 2, 3]}, "c": "x", "d": null});
+        let patch = ops(json!([
+            {"op": "add", "path": "/a/b/0", "value": 0},
+            {"op": "remove", "path": "/c"},
+            {"op": "replace", "path": "/d", "value": {"e": 1}},
+            {"op": "move", "from": "/a/b/3", "path": "/f"},
+            {"op": "copy", "from": "/a", "path": "/a/g"},
+            {"op": "add", "path": "/a/b/-", "value": 4},
+            {"op": "replace", "path": "", "value": [1]},
+            {"op": "add", "path": "/-", "value": 2},
+            {"op": "test", "path": "/1", "value": 3}
+        ]));
+        let mut target = doc.clone();
+        let e = apply(&mut target, &patch).unwrap_err();
+        assert_eq!(format!("{:#}", e), "patch op 8: test /1 FAILED! expected 3, found 2");
+        assert_eq!(target, doc, "失败后应撤销所有修改");
+        // 被删除的 key 放回原来的位置
+        assert_eq!(target.to_string(), doc.to_string());
+
+        // 去掉最后的 test 后正常应用
+        apply(&mut target, &patch[..6]).unwrap();
+        assert_eq!(target, json!({"a": {"b": [0, 1, 2, 4], "g": {"b": [0, 1, 2]}}, "d": {"e": 1}, "f": 3}));
+    }
+
+    #[test]
+    fn test_apply_errors() {
+        let doc = json!({"a": [1, 2], "b": {"c": 1}});
+        let cases = [
+            (json!([{"op": "add", "path": "/a/01", "value": 0}]), "patch op 0: invalid array index \"01\" at /a/01"),
+            (json!([{"op": "add", "path": "/a/3", "value": 0}]), "patch op 0: array index 3 out of range at /a/3"),
+            (json!([{"op": "replace", "path": "/x", "value": 0}]), "patch op 0: path /x not found"),
+            (json!([{"op": "remove", "path": "/a/-"}]), "patch op 0: invalid array index \"-\" at /a/-"),
+            (json!([{"op": "remove", "path": ""}]), "patch op 0: can not remove the root"),
+            (json!([{"op": "move", "from": "/b", "path": "/b/c/d"}]), "patch op 0: can not move /b into its own child /b/c/d"),
+            (json!([{"op": "copy", "from": "/x", "path": "/y"}]), "patch op 0: path /x not found"),
+            (json!([{"op": "move", "from": "/x", "path": "/x"}]), "patch op 0: path /x not found"),
+            (json!([{"op": "test", "path": "/b/c", "value": 1.5}]), "patch op 0: test /b/c FAILED! expected 1.5, found 1"),
+            (json!([{"op": "add", "path": "a", "value": 0}]), "patch op 0: invalid json pointer:\"a\", must start with '/'"),
+        ];
+        for (patch, msg) in cases {
+            let mut target = doc.clone();
+            let e = apply(&mut target, &ops(patch)).unwrap_err();
+            assert_eq!(format!("{:#}", e), msg);
+            assert_eq!(target, doc);
+        }
+
+        // test 按数值比较数字
+        let mut target = doc.clone();
+        apply(&mut target, &ops(json!([{"op": "test", "path": "/a", "value": [1.0, 2]}, {"op": "move", "from": "/b", "path": "/b"}]))).unwrap();
+        assert_eq!(target, doc);
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::anyhow;
+
+// RFC 6901 JSON Pointer 的解析与生成
+
+pub fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+pub fn unescape(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+// "" 表示根，其他必须以 '/' 开头
+pub fn parse(pointer: &str) -> anyhow::Result<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(vec![]);
+    }
+    match pointer.strip_prefix('/') {
+        Some(p) => Ok(p.split('/').map(unescape).collect()),
+        None => Err(anyhow!("invalid json pointer:{:?}, must start with '/'", pointer)),
+    }
+}
+
+// 以 '/' 开头或为空时按 JSON Pointer 解析，否则按 '.' 分隔，如 "server.tls"
+pub fn parse_path(path: &str) -> anyhow::Result<Vec<String>> {
+    if path.is_empty() || path.starts_with('/') {
+        parse(path)
+    } else {
+        Ok(path.split('.').map(|s| s.to_string()).collect())
+    }
+}
+
+pub fn format<S: AsRef<str>>(path: &[S]) -> String {
+    path.iter().map(|k| format!("/{}", escape(k.as_ref()))).collect()
+}
+
+// 路径模式，以 '/' 分隔，开头的 '/' 可以省略，如 "/limits/*"、"**/tags"
+// 与 parse_path 一样也可以用 '.' 分隔，如 "limits.*"；不包含 '/' 时按 '.' 分隔，key 中有 '.' 时需写为 "/a.b"
+pub fn parse_pattern(pattern: &str) -> Vec<String> {
+    if !pattern.contains('/') {
+        return pattern.split('.').filter(|s| !s.is_empty()).map(|s| s.to_string()).collect();
+    }
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+    if pattern.is_empty() {
+        return vec![];
+    }
+    pattern.split('/').map(unescape).collect()
+}
+
+// pattern 中的 "*" 匹配任意一段，"**" 匹配任意多段（包括零段）
+pub fn matches<S: AsRef<str>>(pattern: &[String], path: &[S]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((p, rest)) if p == "**" => (0..=path.len()).any(|i| matches(rest, &path[i..])),
+        Some((p, rest)) => match path.split_first() {
+            Some((k, tail)) => (p == "*" || p == k.as_ref()) && matches(rest, tail),
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format() {
+        assert_eq!(parse("").unwrap(), Vec::<String>::new());
+        assert_eq!(parse("/a/b").unwrap(), vec!["a", "b"]);
+        assert_eq!(parse("/a~1b/m~0n/").unwrap(), vec!["a/b", "m~n", ""]);
+        assert!(parse("a/b").is_err());
+
+        assert_eq!(format(&["a/b", "m~n", "0"]), "/a~1b/m~0n/0");
+        assert_eq!(format::<&str>(&[]), "");
+        // ~01 应解析为 ~1 而不是 /
+        assert_eq!(parse("/~01").unwrap(), vec!["~1"]);
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(parse_path("server.tls").unwrap(), vec!["server", "tls"]);
+        assert_eq!(parse_path("/server/tls").unwrap(), vec!["server", "tls"]);
+        assert_eq!(parse_path("").unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_matches() {
+        let pattern = parse("/servers/*/tags").unwrap();
+        assert!(matches(&pattern, &["servers", "0", "tags"]));
+        assert!(matches(&pattern, &["servers", "web", "tags"]));
+        assert!(!matches(&pattern, &["servers", "tags"]));
+        assert!(!matches(&pattern, &["servers", "0", "tags", "0"]));
+    }
+
+    #[test]
+    fn test_matches_recursive() {
+        let pattern = parse_pattern("**/tags");
+        assert_eq!(pattern, vec!["**", "tags"]);
+        assert!(matches(&pattern, &["tags"]));
+        assert!(matches(&pattern, &["a", "b", "tags"]));
+        assert!(!matches(&pattern, &["a", "tags", "b"]));
+
+        let pattern = parse_pattern("/limits/**/max");
+        assert!(matches(&pattern, &["limits", "max"]));
+        assert!(matches(&pattern, &["limits", "api", "user", "max"]));
+        assert!(!matches(&pattern, &["api", "limits", "max"]));
+
+        // 不包含 '/' 时按 '.' 分隔
+        assert_eq!(parse_pattern("api.desc"), vec!["api", "desc"]);
+        assert_eq!(parse_pattern("**.tags"), vec!["**", "tags"]);
+        assert_eq!(parse_pattern("/a.b"), vec!["a.b"]);
+
+        assert!(matches(&parse_pattern("**"), &["a", "b"]));
+        assert!(matches(&parse_pattern(""), &[] as &[&str]));
+    }
+}
@@ -0,0 +1,173 @@
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_json::{Map, Number, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// 子树通过 Arc 共享的 JSON 值，clone 只增加引用计数
+// 用于一个大的 base 配置叠加许多小的 overlay（如每个租户一份），合并结果中没有修改的子树与 base 共享内存
+// 对象的 key 总是按字母顺序保存，不受 preserve_order 影响
+#[derive(Debug, Clone, PartialEq)]
+pub enum SharedValue {
+    Null,
+    Bool(bool),
+    Number(Number),
+    String(Arc<str>),
+    Array(Arc<Vec<SharedValue>>),
+    Object(Arc<BTreeMap<String, SharedValue>>),
+}
+
+impl SharedValue {
+    pub fn get(&self, key: &str) -> Option<&SharedValue> {
+        match self {
+            SharedValue::Object(obj) => obj.get(key),
+            SharedValue::Array(arr) => key.parse::<usize>().ok().and_then(|i| arr.get(i)),
+            _ => None,
+        }
+    }
+
+    // pointer 为 JSON Pointer，格式错误或路径不存在时返回 None
+    pub fn pointer(&self, pointer: &str) -> Option<&SharedValue> {
+        let path = crate::json_pointer::parse(pointer).ok()?;
+        path.iter().try_fold(self, |v, k| v.get(k))
+    }
+
+    // 两个值是否为同一份数据，只比较字符串、数组、对象的指针，其他类型返回 false
+    pub fn ptr_eq(&self, other: &SharedValue) -> bool {
+        match (self, other) {
+            (SharedValue::String(a), SharedValue::String(b)) => Arc::ptr_eq(a, b),
+            (SharedValue::Array(a), SharedValue::Array(b)) => Arc::ptr_eq(a, b),
+            (SharedValue::Object(a), SharedValue::Object(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    // 与 json_merge::merge 规则相同，返回新值，self 和 overlay 不变
+    // 只复制从根到修改位置路径上的对象，其余子树与 self、overlay 共享
+    pub fn merged(&self, overlay: &SharedValue) -> SharedValue {
+        let (base, patch) = match (self, overlay) {
+            (SharedValue::Object(base), SharedValue::Object(patch)) => (base, patch),
+            _ => return overlay.clone(),
+        };
+
+        let mut obj = (**base).clone();
+        for (k, v) in patch.iter() {
+            if *v == SharedValue::Null {
+                obj.remove(k);
+                continue;
+            }
+            let new = match obj.get(k) {
+                Some(old) => old.merged(v),
+                None => v.clone(),
+            };
+            obj.insert(k.clone(), new);
+        }
+        SharedValue::Object(Arc::new(obj))
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            SharedValue::Null => Value::Null,
+            SharedValue::Bool(b) => Value::Bool(*b),
+            SharedValue::Number(n) => Value::Number(n.clone()),
+            SharedValue::String(s) => Value::String(s.to_string()),
+            SharedValue::Array(arr) => Value::Array(arr.iter().map(|v| v.to_value()).collect()),
+            SharedValue::Object(obj) => {
+                Value::Object(obj.iter().map(|(k, v)| (k.clone(), v.to_value())).collect::<Map<_, _>>())
+            }
+        }
+    }
+}
+
+impl From<Value> for SharedValue {
+    fn from(v: Value) -> Self {
+        match v {
+            Value::Null => SharedValue::Null,
+            Value::Bool(b) => SharedValue::Bool(b),
+            Value::Number(n) => SharedValue::Number(n),
+            Value::String(s) => SharedValue::String(s.into()),
+            Value::Array(arr) => SharedValue::Array(Arc::new(arr.into_iter().map(SharedValue::from).collect())),
+            Value::Object(obj) => {
+                SharedValue::Object(Arc::new(obj.into_iter().map(|(k, v)| (k, SharedValue::from(v))).collect()))
+            }
+        }
+    }
+}
+
+impl From<&SharedValue> for Value {
+    fn from(v: &SharedValue) -> Self {
+        v.to_value()
+    }
+}
+
+impl Serialize for SharedValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            SharedValue::Null => serializer.serialize_unit(),
+            SharedValue::Bool(b) => serializer.serialize_bool(*b),
+            SharedValue::Number(n) => n.serialize(serializer),
+            SharedValue::String(s) => serializer.serialize_str(s),
+            SharedValue::Array(arr) => {
+                let mut seq = serializer.serialize_seq(Some(arr.len()))?;
+                for v in arr.iter() {
+                    seq.serialize_element(v)?;
+                }
+                seq.end()
+            }
+            SharedValue::Object(obj) => {
+                let mut map = serializer.serialize_map(Some(obj.len()))?;
+                for (k, v) in obj.iter() {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::json_merge;
+    use serde_json::json;
+
+    #[test]
+    fn test_merged_shares_base() {
+        let base_value = json!({
+            "server": {"host": "0.0.0.0", "port": 80},
+            "limits": {"rate": 100, "burst": 10},
+            "routes": [{"path": "/", "upstream": "web"}]
+        });
+        let base = SharedValue::from(base_value.clone());
+
+        let overlays = [
+            json!({"limits": {"rate": 50}}),
+            json!({"limits": {"burst": null}, "tenant": "b"}),
+            json!({"server": {"port": 8080}}),
+        ];
+        for overlay in overlays {
+            let v = base.merged(&SharedValue::from(overlay.clone()));
+            // 结果与 json_merge::merged 相同
+            assert_eq!(v.to_value(), json_merge::merged(&base_value, &overlay), "overlay:{}", overlay);
+            // 没有修改的子树与 base 共享
+            assert!(v.get("routes").unwrap().ptr_eq(base.get("routes").unwrap()), "routes 应共享");
+            assert!(!v.ptr_eq(&base), "根对象应为新对象");
+        }
+
+        let v = base.merged(&SharedValue::from(json!({"limits": {"rate": 50}})));
+        assert!(v.get("server").unwrap().ptr_eq(base.get("server").unwrap()), "server 应共享");
+        assert!(!v.get("limits").unwrap().ptr_eq(base.get("limits").unwrap()), "limits 应为新对象");
+        assert_eq!(v.pointer("/limits/rate"), Some(&SharedValue::from(json!(50))));
+        assert_eq!(v.pointer("/routes/0/upstream"), Some(&SharedValue::from(json!("web"))));
+
+        // base 不变
+        assert_eq!(base.to_value(), base_value);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let v = json!({"a": [1, "x", null, true], "b": {"c": 1.5}});
+        let shared = SharedValue::from(v.clone());
+        assert_eq!(serde_json::to_value(&shared).unwrap(), v);
+        assert_eq!(Value::from(&shared), v);
+    }
+}
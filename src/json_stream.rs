@@ -0,0 +1,506 @@
+use anyhow::{anyhow, Context};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+// 流式合并大 JSON 文件，规则与 json_merge::merge 相同：overlay 中的 null 删除对应的 key，数组整体替换
+// 先扫描一遍 overlay，只记录对象的 key 和每个值在文件中的位置，然后逐个读取 base 的 token 并写出结果：
+// overlay 没有涉及的部分原样复制，被替换的值从 overlay 的对应位置复制，两边都是对象时逐层合并
+// 内存占用只与 overlay 中对象的 key 数量有关，输出为紧凑格式，base 中已有的 key 保持原顺序，新增的 key 按 overlay 的顺序追加
+pub fn merge<B, P, W>(base: B, overlay: P, out: W) -> anyhow::Result<()>
+where
+    B: Read,
+    P: Read + Seek,
+    W: Write,
+{
+    let mut overlay = Scanner::new(overlay);
+    let patch = index(&mut overlay)?;
+    overlay.expect_end()?;
+
+    let mut base = Scanner::new(base);
+    let mut out = BufWriter::new(out);
+    merge_value(&mut base, &mut overlay, &patch, &mut out)?;
+    base.expect_end()?;
+    out.flush()?;
+    Ok(())
+}
+
+pub fn merge_files(base: &str, overlay: &str, out: &str) -> anyhow::Result<()> {
+    let b = std::fs::File::open(base).context(format!("open base {}", base))?;
+    let p = std::fs::File::open(overlay).context(format!("open overlay {}", overlay))?;
+    let o = std::fs::File::create(out).context(format!("create {}", out))?;
+    merge(b, p, o).context(format!("merge {} and {} into {}", base, overlay, out))
+}
+
+// overlay 中的一个值，start 为它在文件中的起始位置
+struct Patch {
+    start: u64,
+    kind: PatchKind,
+}
+
+enum PatchKind {
+    Null,
+    // key 按 overlay 中的顺序保存，重复的 key 以最后一个为准
+    Object(Vec<(String, Patch)>, HashMap<String, usize>),
+    Other,
+}
+
+fn index<R: Read>(s: &mut Scanner<R>) -> anyhow::Result<Patch> {
+    s.skip_ws()?;
+    let start = s.pos;
+    let kind = match s.peek()? {
+        Some(b'{') => {
+            s.bump();
+            let mut keys: Vec<(String, Patch)> = vec![];
+            let mut positions: HashMap<String, usize> = HashMap::new();
+            if !s.empty_container(b'}')? {
+                loop {
+                    let key = s.read_key()?;
+                    let child = index(s)?;
+                    match positions.get(&key) {
+                        Some(&i) => keys[i].1 = child,
+                        None => {
+                            positions.insert(key.clone(), keys.len());
+                            keys.push((key, child));
+                        }
+                    }
+                    if !s.next_member(b'}')? {
+                        break;
+                    }
+                }
+            }
+            PatchKind::Object(keys, positions)
+        }
+        Some(b'n') => {
+            s.copy_value(&mut io::sink())?;
+            PatchKind::Null
+        }
+        _ => {
+            s.copy_value(&mut io::sink())?;
+            PatchKind::Other
+        }
+    };
+    Ok(Patch { start, kind })
+}
+
+fn merge_value<B, P, W>(base: &mut Scanner<B>, overlay: &mut Scanner<P>, patch: &Patch, out: &mut W) -> anyhow::Result<()>
+where
+    B: Read,
+    P: Read + Seek,
+    W: Write,
+{
+    base.skip_ws()?;
+    match &patch.kind {
+        PatchKind::Object(keys, positions) if base.peek()? == Some(b'{') => {
+            merge_object(base, overlay, keys, positions, out)
+        }
+        _ => {
+            base.copy_value(&mut io::sink())?;
+            overlay.seek(patch.start)?;
+            overlay.copy_value(out)
+        }
+    }
+}
+
+fn merge_object<B, P, W>(
+    base: &mut Scanner<B>,
+    overlay: &mut Scanner<P>,
+    keys: &[(String, Patch)],
+    positions: &HashMap<String, usize>,
+    out: &mut W,
+) -> anyhow::Result<()>
+where
+    B: Read,
+    P: Read + Seek,
+    W: Write,
+{
+    base.bump();
+    out.write_all(b"{")?;
+    let mut seen = vec![false; keys.len()];
+    let mut first = true;
+
+    if !base.empty_container(b'}')? {
+        loop {
+            let key = base.read_key()?;
+            match positions.get(&key) {
+                None => {
+                    write_key(out, &key, &mut first)?;
+                    base.copy_value(out)?;
+                }
+                Some(&i) => {
+                    seen[i] = true;
+                    let patch = &keys[i].1;
+                    if let PatchKind::Null = patch.kind {
+                        base.copy_value(&mut io::sink())?;
+                    } else {
+                        write_key(out, &key, &mut first)?;
+                        merge_value(base, overlay, patch, out)?;
+                    }
+                }
+            }
+            if !base.next_member(b'}')? {
+                break;
+            }
+        }
+    }
+
+    // base 中没有的 key
+    for ((key, patch), seen) in keys.iter().zip(seen) {
+        if seen || matches!(patch.kind, PatchKind::Null) {
+            continue;
+        }
+        write_key(out, key, &mut first)?;
+        overlay.seek(patch.start)?;
+        overlay.copy_value(out)?;
+    }
+    out.write_all(b"}")?;
+    Ok(())
+}
+
+fn write_key<W: Write>(out: &mut W, key: &str, first: &mut bool) -> anyhow::Result<()> {
+    if !*first {
+        out.write_all(b",")?;
+    }
+    *first = false;
+    serde_json::to_writer(&mut *out, key)?;
+    out.write_all(b":")?;
+    Ok(())
+}
+
+// 按字节读取 JSON，pos 为下一个字节在输入中的位置
+struct Scanner<R> {
+    r: BufReader<R>,
+    pos: u64,
+}
+
+impl<R: Read> Scanner<R> {
+    fn new(r: R) -> Self {
+        Scanner { r: BufReader::new(r), pos: 0 }
+    }
+
+    fn error(&self, msg: &str) -> anyhow::Error {
+        anyhow!("{} at byte {}", msg, self.pos)
+    }
+
+    fn peek(&mut self) -> anyhow::Result<Option<u8>> {
+        Ok(self.r.fill_buf()?.first().copied())
+    }
+
+    fn bump(&mut self) {
+        self.r.consume(1);
+        self.pos += 1;
+    }
+
+    fn next(&mut self) -> anyhow::Result<u8> {
+        match self.peek()? {
+            Some(c) => {
+                self.bump();
+                Ok(c)
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn skip_ws(&mut self) -> anyhow::Result<()> {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek()? {
+            self.bump();
+        }
+        Ok(())
+    }
+
+    fn expect(&mut self, expected: u8) -> anyhow::Result<()> {
+        self.skip_ws()?;
+        match self.peek()? {
+            Some(c) if c == expected => {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => Err(self.error(&format!("expected {:?}, found {:?}", expected as char, c as char))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn expect_end(&mut self) -> anyhow::Result<()> {
+        self.skip_ws()?;
+        match self.peek()? {
+            None => Ok(()),
+            Some(_) => Err(self.error("trailing characters")),
+        }
+    }
+
+    // 刚读完 '{' 或 '['，容器为空时读掉结束符并返回 true
+    fn empty_container(&mut self, close: u8) -> anyhow::Result<bool> {
+        self.skip_ws()?;
+        if self.peek()? == Some(close) {
+            self.bump();
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    // 读取成员之间的 ','，遇到结束符时返回 false
+    fn next_member(&mut self, close: u8) -> anyhow::Result<bool> {
+        self.skip_ws()?;
+        match self.next()? {
+            b',' => Ok(true),
+            c if c == close => Ok(false),
+            c => Err(self.error(&format!("expected ',' or {:?}, found {:?}", close as char, c as char))),
+        }
+    }
+
+    // 读取对象的 key 和之后的 ':'
+    fn read_key(&mut self) -> anyhow::Result<String> {
+        self.skip_ws()?;
+        if self.peek()? != Some(b'"') {
+            return Err(self.error("expected object key"));
+        }
+        let mut buf = vec![];
+        self.copy_string(&mut buf)?;
+        let key = serde_json::from_slice(&buf).map_err(|e| self.error(&format!("invalid key: {}", e)))?;
+        self.expect(b':')?;
+        Ok(key)
+    }
+
+    // 读取对象的 key 和之后的 ':'，原样写到 out
+    fn copy_key<W: Write>(&mut self, out: &mut W) -> anyhow::Result<()> {
+        self.skip_ws()?;
+        if self.peek()? != Some(b'"') {
+            return Err(self.error("expected object key"));
+        }
+        self.copy_string(out)?;
+        self.expect(b':')?;
+        out.write_all(b":")?;
+        Ok(())
+    }
+
+    fn copy_string<W: Write>(&mut self, out: &mut W) -> anyhow::Result<()> {
+        self.bump();
+        out.write_all(b"\"")?;
+        loop {
+            let c = self.next()?;
+            out.write_all(&[c])?;
+            match c {
+                b'\\' => {
+                    let e = self.next()?;
+                    out.write_all(&[e])?;
+                    match e {
+                        b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => {}
+                        b'u' => {
+                            for _ in 0..4 {
+                                let h = self.next()?;
+                                if !h.is_ascii_hexdigit() {
+                                    return Err(self.error("invalid unicode escape"));
+                                }
+                                out.write_all(&[h])?;
+                            }
+                        }
+                        _ => return Err(self.error(&format!("invalid escape {:?}", e as char))),
+                    }
+                }
+                b'"' => return Ok(()),
+                c if c < 0x20 => return Err(self.error("control character in string")),
+                _ => {}
+            }
+        }
+    }
+
+    fn copy_literal<W: Write>(&mut self, literal: &[u8], out: &mut W) -> anyhow::Result<()> {
+        for &l in literal {
+            if self.peek()? != Some(l) {
+                return Err(self.error(&format!("invalid literal, expected {}", String::from_utf8_lossy(literal))));
+            }
+            self.bump();
+        }
+        out.write_all(literal)?;
+        Ok(())
+    }
+
+    // -?(0|[1-9][0-9]*)(.[0-9]+)?([eE][+-]?[0-9]+)?
+    fn copy_number<W: Write>(&mut self, out: &mut W) -> anyhow::Result<()> {
+        if self.peek()? == Some(b'-') {
+            self.bump();
+            out.write_all(b"-")?;
+        }
+        match self.peek()? {
+            Some(b'0') => {
+                self.bump();
+                out.write_all(b"0")?;
+            }
+            Some(b'1'..=b'9') => {
+                self.copy_digits(out)?;
+            }
+            _ => return Err(self.error("invalid number")),
+        }
+        if self.peek()? == Some(b'.') {
+            self.bump();
+            out.write_all(b".")?;
+            if self.copy_digits(out)? == 0 {
+                return Err(self.error("invalid number"));
+            }
+        }
+        if let Some(c @ (b'e' | b'E')) = self.peek()? {
+            self.bump();
+            out.write_all(&[c])?;
+            if let Some(c @ (b'+' | b'-')) = self.peek()? {
+                self.bump();
+                out.write_all(&[c])?;
+            }
+            if self.copy_digits(out)? == 0 {
+                return Err(self.error("invalid number"));
+            }
+        }
+        Ok(())
+    }
+
+    fn copy_digits<W: Write>(&mut self, out: &mut W) -> anyhow::Result<usize> {
+        let mut n = 0;
+        while let Some(c @ b'0'..=b'9') = self.peek()? {
+            self.bump();
+            out.write_all(&[c])?;
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    // 读取一个完整的值并写到 out，去掉字符串以外的空白，同时检查语法，不使用递归
+    fn copy_value<W: Write>(&mut self, out: &mut W) -> anyhow::Result<()> {
+        // 未结束的容器，true 为对象
+        let mut stack: Vec<bool> = vec![];
+        loop {
+            self.skip_ws()?;
+            match self.peek()? {
+                Some(c @ (b'{' | b'[')) => {
+                    self.bump();
+                    out.write_all(&[c])?;
+                    let close = if c == b'{' { b'}' } else { b']' };
+                    if self.empty_container(close)? {
+                        out.write_all(&[close])?;
+                    } else {
+                        stack.push(c == b'{');
+                        if c == b'{' {
+                            self.copy_key(out)?;
+                        }
+                        continue;
+                    }
+                }
+                Some(b'"') => self.copy_string(out)?,
+                Some(b't') => self.copy_literal(b"true", out)?,
+                Some(b'f') => self.copy_literal(b"false", out)?,
+                Some(b'n') => self.copy_literal(b"null", out)?,
+                Some(b'-' | b'0'..=b'9') => self.copy_number(out)?,
+                Some(c) => return Err(self.error(&format!("unexpected {:?}", c as char))),
+                None => return Err(self.error("unexpected end of input")),
+            }
+
+            // 一个值结束，读取 ',' 或结束符
+            loop {
+                let is_object = match stack.last() {
+                    Some(&o) => o,
+                    None => return Ok(()),
+                };
+                let close = if is_object { b'}' } else { b']' };
+                if self.next_member(close)? {
+                    out.write_all(b",")?;
+                    if is_object {
+                        self.copy_key(out)?;
+                    }
+                    break;
+                }
+                out.write_all(&[close])?;
+                stack.pop();
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> Scanner<R> {
+    fn seek(&mut self, pos: u64) -> anyhow::Result<()> {
+        self.r.seek(SeekFrom::Start(pos))?;
+        self.pos = pos;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::json_merge;
+    use serde_json::{json, Value};
+    use std::io::Cursor;
+
+    fn stream_merge(base: &str, overlay: &str) -> anyhow::Result<Value> {
+        let mut out = vec![];
+        merge(base.as_bytes(), Cursor::new(overlay.as_bytes()), &mut out)?;
+        Ok(serde_json::from_slice(&out)?)
+    }
+
+    #[test]
+    fn test_merge_same_as_json_merge() {
+        let cases = [
+            (json!({"a": 1, "b": {"c": 2, "d": [1, 2]}}), json!({"b": {"c": null, "d": [3], "e": {"f": null}}, "g": "x"})),
+            (json!({"a": {"b": {"c": 1}}}), json!({"a": {"b": "s"}})),
+            (json!({"a": "s"}), json!({"a": {"b": 1}})),
+            (json!({"a": 1}), json!([1, 2])),
+            (json!([1, 2]), json!({"a": 1, "b": null})),
+            (json!({"a": 1}), json!(null)),
+            (json!({}), json!({})),
+            (json!({"a\"b": 1, "ü": {"x": 1}}), json!({"ü": {"y": 2}, "a\"b": null})),
+        ];
+        for (a, b) in cases {
+            let mut expected = a.clone();
+            json_merge::merge(&mut expected, b.clone());
+            // 带缩进的输入
+            let base = serde_json::to_string_pretty(&a).unwrap();
+            let overlay = serde_json::to_string_pretty(&b).unwrap();
+            assert_eq!(stream_merge(&base, &overlay).unwrap(), expected, "merge({}, {})", a, b);
+        }
+    }
+
+    #[test]
+    fn test_merge_raw_text() {
+        // 转义字符、数字格式、重复的 key 保持 serde_json 的语义
+        let base = r#" {"s": "a\"\\é", "n": -1.5e10, "arr": [ {"x": [ ]}, "}" ], "k": 1} "#;
+        let overlay = r#"{"k": 2, "k": 3, "new": {"t": true, "f": false, "z": null}}"#;
+        let mut out = vec![];
+        merge(base.as_bytes(), Cursor::new(overlay.as_bytes()), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"{"s":"a\"\\é","n":-1.5e10,"arr":[{"x":[]},"}"],"k":3,"new":{"t":true,"f":false,"z":null}}"#
+        );
+    }
+
+    #[test]
+    fn test_merge_large_array() {
+        let base = json!({"items": (0..10000).map(|i| json!({"id": i})).collect::<Vec<_>>(), "v": 1});
+        let r = stream_merge(&base.to_string(), r#"{"v": 2}"#).unwrap();
+        assert_eq!(r["v"], json!(2));
+        assert_eq!(r["items"], base["items"]);
+    }
+
+    #[test]
+    fn test_merge_invalid() {
+        assert!(stream_merge(r#"{"a": 1"#, "{}").is_err());
+        assert!(stream_merge(r#"{"a": [1}"#, r#"{"b": 1}"#).is_err());
+        assert!(stream_merge(r#"{"a": 1} x"#, "{}").is_err());
+        assert!(stream_merge("{}", r#"{"a": nul}"#).is_err());
+        assert!(stream_merge("{}", r#"{"a" 1}"#).is_err());
+
+        // 不合法的字面量、数字和分隔符
+        for base in [
+            r#"{"a": tru}"#,
+            r#"{"a": [1,,2]}"#,
+            r#"{"a": {"b" 1}}"#,
+            r#"{"a": hello}"#,
+            r#"{"a": [1 2]}"#,
+            r#"{"a": {"b": 1,}}"#,
+            r#"{"a": 01}"#,
+            r#"{"a": 1.}"#,
+            r#"{"a": -}"#,
+            r#"{"a": 1e}"#,
+            r#"{"a": "\x"}"#,
+            r#"{"a": [1]]}"#,
+        ] {
+            assert!(stream_merge(base, r#"{"b": 1}"#).is_err(), "base:{}", base);
+        }
+        assert_eq!(stream_merge(r#"{"a": [-0.5e+3, 0, "\u00e9\n"]}"#, "{}").unwrap(), json!({"a": [-500.0, 0, "é\n"]}));
+    }
+}
@@ -1,12 +1,33 @@
+// derive 生成的代码通过 ::rsutils 引用本 crate
+extern crate self as rsutils;
+
 pub mod misc;
 
 #[cfg(feature = "json")]
 pub mod json_merge;
 #[cfg(feature = "json")]
 pub mod json_diff;
+#[cfg(feature = "json")]
+pub mod json_pointer;
+#[cfg(feature = "json")]
+mod json_deep;
+#[cfg(feature = "json")]
+pub mod merge_patch;
+#[cfg(feature = "json")]
+pub mod json_shared;
+#[cfg(feature = "json")]
+pub mod json_stream;
+#[cfg(feature = "json")]
+pub mod json_lines;
+#[cfg(feature = "json")]
+pub mod json_patch;
 
 #[cfg(feature = "config")]
 pub mod config;
+#[cfg(feature = "config")]
+pub mod config_doc;
+#[cfg(feature = "tokio")]
+pub mod config_async;
 
 #[cfg(feature = "datetime")]
 pub mod datetime;
\ No newline at end of file
@@ -0,0 +1,174 @@
+use anyhow::anyhow;
+use serde_json::{Map, Value};
+
+use crate::{json_deep, json_pointer};
+
+// 严格按照 RFC 7386 JSON Merge Patch 实现
+// 与 json_merge::merge 的区别：patch 为对象而 target 不是对象时，先将 target 置为 {} 再逐个应用，
+// 因此新插入的对象中的 null 也会被去掉
+pub fn apply(target: &mut Value, patch: Value) {
+    match patch {
+        Value::Object(patch) => {
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            let obj = target.as_object_mut().unwrap();
+            for (k, v) in patch {
+                if v.is_null() {
+                    json_deep::remove_key(obj, &k);
+                } else {
+                    apply(obj.entry(k).or_insert(Value::Null), v);
+                }
+            }
+        }
+        patch => *target = patch,
+    }
+}
+
+// 生成将 source 变为 target 的 merge patch，满足 apply(source, generate(source, target)) == target
+// target 的对象中新出现的 null 成员无法用 merge patch 表达，此时返回错误
+pub fn generate(source: &Value, target: &Value) -> anyhow::Result<Value> {
+    generate_path(source, target, &mut vec![])
+}
+
+fn generate_path(source: &Value, target: &Value, path: &mut Vec<String>) -> anyhow::Result<Value> {
+    let obj_t = match target {
+        Value::Object(obj) => obj,
+        _ => return Ok(target.clone()),
+    };
+    let empty = Map::new();
+    let obj_s = source.as_object().unwrap_or(&empty);
+
+    let mut patch = Map::new();
+    for k in obj_s.keys() {
+        if !obj_t.contains_key(k) {
+            patch.insert(k.clone(), Value::Null);
+        }
+    }
+    for (k, v_t) in obj_t {
+        path.push(k.clone());
+        match obj_s.get(k) {
+            Some(v_s) if v_s == v_t => {}
+            _ if v_t.is_null() => {
+                return Err(anyhow!("null at {} can not be expressed by merge patch", json_pointer::format(path)));
+            }
+            Some(v_s) => {
+                patch.insert(k.clone(), generate_path(v_s, v_t, path)?);
+            }
+            None => {
+                patch.insert(k.clone(), generate_path(&Value::Null, v_t, path)?);
+            }
+        }
+        path.pop();
+    }
+
+    Ok(Value::Object(patch))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    // RFC 7386 Appendix A
+    fn rfc_vectors() -> Vec<(Value, Value, Value)> {
+        vec![
+            (json!({"a": "b"}), json!({"a": "c"}), json!({"a": "c"})),
+            (json!({"a": "b"}), json!({"b": "c"}), json!({"a": "b", "b": "c"})),
+            (json!({"a": "b"}), json!({"a": null}), json!({})),
+            (json!({"a": "b", "b": "c"}), json!({"a": null}), json!({"b": "c"})),
+            (json!({"a": ["b"]}), json!({"a": "c"}), json!({"a": "c"})),
+            (json!({"a": "c"}), json!({"a": ["b"]}), json!({"a": ["b"]})),
+            (json!({"a": {"b": "c"}}), json!({"a": {"b": "d", "c": null}}), json!({"a": {"b": "d"}})),
+            (json!({"a": [{"b": "c"}]}), json!({"a": [1]}), json!({"a": [1]})),
+            (json!(["a", "b"]), json!(["c", "d"]), json!(["c", "d"])),
+            (json!({"a": "b"}), json!(["c"]), json!(["c"])),
+            (json!({"a": "foo"}), json!(null), json!(null)),
+            (json!({"a": "foo"}), json!("bar"), json!("bar")),
+            (json!({"e": null}), json!({"a": 1}), json!({"e": null, "a": 1})),
+            (json!([1, 2]), json!({"a": "b", "c": null}), json!({"a": "b"})),
+            (json!({}), json!({"a": {"bb": {"ccc": null}}}), json!({"a": {"bb": {}}})),
+        ]
+    }
+
+    #[test]
+    fn test_apply_rfc_vectors() {
+        for (original, patch, expected) in rfc_vectors() {
+            let mut target = original.clone();
+            apply(&mut target, patch.clone());
+            assert_eq!(target, expected, "apply({}, {})", original, patch);
+        }
+    }
+
+    #[test]
+    fn test_apply_rfc_example() {
+        // RFC 7386 Section 3
+        let mut target = json!({
+            "title": "Goodbye!",
+            "author": {
+                "givenName": "John",
+                "familyName": "Doe"
+            },
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged"
+        });
+        let patch = json!({
+            "title": "Hello!",
+            "phoneNumber": "+01-123-456-7890",
+            "author": {
+                "familyName": null
+            },
+            "tags": ["example"]
+        });
+
+        apply(&mut target, patch);
+
+        let expected = json!({
+            "title": "Hello!",
+            "author": {
+                "givenName": "John"
+            },
+            "tags": ["example"],
+            "content": "This will be unchanged",
+            "phoneNumber": "+01-123-456-7890"
+        });
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn test_generate_round_trip() {
+        // generate 生成的 patch 应能还原 RFC 用例的结果
+        for (original, _, expected) in rfc_vectors() {
+            let patch = generate(&original, &expected).unwrap();
+            let mut target = original.clone();
+            apply(&mut target, patch.clone());
+            assert_eq!(target, expected, "generate({}, {}) = {}", original, expected, patch);
+        }
+
+        assert_eq!(generate(&json!({"a": 1}), &json!({"a": 1})).unwrap(), json!({}));
+        assert_eq!(
+            generate(&json!({"a": {"b": 1, "c": 2}}), &json!({"a": {"b": 1}, "d": [null]})).unwrap(),
+            json!({"a": {"c": null}, "d": [null]})
+        );
+    }
+
+    #[test]
+    fn test_generate_null_member() {
+        let r = generate(&json!({}), &json!({"a": {"b": null}}));
+        assert_eq!(r.unwrap_err().to_string(), "null at /a/b can not be expressed by merge patch");
+    }
+
+    #[test]
+    fn test_differs_from_json_merge() {
+        // json_merge::merge 会保留新插入对象中的 null，RFC 7386 要求去掉
+        let patch = json!({"a": {"bb": {"ccc": null}}});
+
+        let mut a = json!({});
+        crate::json_merge::merge(&mut a, patch.clone());
+        assert_eq!(a, json!({"a": {"bb": {"ccc": null}}}));
+
+        let mut a = json!({});
+        apply(&mut a, patch);
+        assert_eq!(a, json!({"a": {"bb": {}}}));
+    }
+}